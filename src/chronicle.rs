@@ -0,0 +1,233 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::config::SimConfig;
+use crate::events::{BoatLanded, CellCaptured, EmpireExtinct, LandmassInvaded, TechAdvanced, Territory};
+use crate::landmass::Landmasses;
+use crate::map::EmpireId;
+use crate::GameData;
+
+//early on every cell is its own empire, so only empires that grew this big are worth writing about
+const NOTABLE_SIZE: u32 = 100;
+//the first invasion of a landmass is only news if it has room for more than a beachhead, islets are reached all the time
+const NOTABLE_LANDMASS: usize = 100;
+//conquests are summed over windows of this many ticks. Borders trade cells back and forth all the time,
+//so only what one empire took from another minus what it lost back counts.
+const CONQUEST_WINDOW: u64 = 50;
+const CONQUEST_CELLS: u32 = 100;
+//how many entries the in-app feed keeps
+const FEED_LENGTH: usize = 200;
+const FEED_LINES: usize = 12;
+
+#[derive(Resource)]
+pub struct Chronicle {
+    pub feed: VecDeque<(u64, String)>,
+    log: Option<BufWriter<File>>,
+    //the most cells each empire has ever held
+    peak: Vec<u32>,
+    leader: Option<EmpireId>,
+    //cells taken in the current window, by (conqueror, victim)
+    conquests: HashMap<(EmpireId, EmpireId), u32>,
+    first_landing: bool,
+    pub visible: bool,
+}
+
+impl Chronicle {
+    pub fn new(log: Option<&Path>) -> std::io::Result<Self> {
+        let log = match log {
+            Some(path) => {
+                if let Some(dir) = path.parent() {
+                    if !dir.as_os_str().is_empty() {
+                        std::fs::create_dir_all(dir)?;
+                    }
+                }
+                Some(BufWriter::new(File::create(path)?))
+            }
+            None => None,
+        };
+        Ok(Chronicle {
+            feed: VecDeque::new(),
+            log,
+            peak: Vec::new(),
+            leader: None,
+            conquests: HashMap::default(),
+            first_landing: false,
+            visible: true,
+        })
+    }
+
+    fn record(&mut self, tick: u64, text: String) {
+        if let Some(log) = &mut self.log {
+            if let Err(e) = writeln!(log, "tick {}: {}", tick, text) {
+                eprintln!("Could not write to the chronicle: {}", e);
+                self.log = None;
+            }
+        }
+        if self.feed.len() == FEED_LENGTH {
+            self.feed.pop_front();
+        }
+        self.feed.push_back((tick, text));
+    }
+
+    //everything but the log, for going back to with a rewind
+    pub fn remember(&self) -> Chronicle {
+        Chronicle {
+            feed: self.feed.clone(),
+            log: None,
+            peak: self.peak.clone(),
+            leader: self.leader,
+            conquests: self.conquests.clone(),
+            first_landing: self.first_landing,
+            visible: self.visible,
+        }
+    }
+
+    //pick up from a remembered chronicle. What's already in the log stays there, so it says where history went back to.
+    pub fn rewind(&mut self, to: &Chronicle, tick: u64, text: String) {
+        self.feed.clone_from(&to.feed);
+        self.peak.clone_from(&to.peak);
+        self.leader = to.leader;
+        self.conquests.clone_from(&to.conquests);
+        self.first_landing = to.first_landing;
+        self.record(tick, text);
+    }
+
+    fn notable(&self, empire: EmpireId) -> bool {
+        self.peak.get(empire.index()).is_some_and(|peak| *peak >= NOTABLE_SIZE)
+    }
+}
+
+//runs at the end of every tick, before the tick counter moves on
+#[allow(clippy::too_many_arguments)]
+pub fn chronicle_system(
+    mut chronicle: ResMut<Chronicle>,
+    territory: Res<Territory>,
+    game_data: Res<GameData>,
+    config: Res<SimConfig>,
+    mut extinct: EventReader<EmpireExtinct>,
+    mut captured: EventReader<CellCaptured>,
+    mut landed: EventReader<BoatLanded>,
+    mut invaded: EventReader<LandmassInvaded>,
+    mut advanced: EventReader<TechAdvanced>,
+    landmasses: Res<Landmasses>,
+) {
+    let tick = game_data.tick;
+    let chronicle = &mut *chronicle;
+
+    //extinctions are checked against the peak before it's updated, an empire that died this tick still had its old size
+    for event in extinct.read() {
+        if chronicle.notable(event.empire) {
+            let text = format!("Empire {} has fallen, its last cell at ({}, {}) lost. At its height it held {} cells.", event.empire, event.position.0, event.position.1, chronicle.peak[event.empire.index()]);
+            chronicle.record(event.tick, text);
+            //a dying empire can win a stray cell back for a tick or two, it only falls once unless it grows big again
+            chronicle.peak[event.empire.index()] = 0;
+        }
+    }
+    if chronicle.peak.len() < territory.0.len() {
+        chronicle.peak.resize(territory.0.len(), 0);
+    }
+    for (peak, cells) in chronicle.peak.iter_mut().zip(&territory.0) {
+        *peak = (*peak).max(*cells);
+    }
+
+    //the largest empire, ties go to the lower id so the leader doesn't flicker
+    let leader = territory.0.iter().enumerate().max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(&a.0))).map(|(index, cells)| (EmpireId(index as u32), *cells));
+    if let Some((leader, cells)) = leader {
+        if cells >= NOTABLE_SIZE && chronicle.leader != Some(leader) {
+            let text = match chronicle.leader {
+                Some(previous) => format!("Empire {} overtakes Empire {} as the largest empire with {} cells.", leader, previous, cells),
+                None => format!("Empire {} is the largest empire with {} cells.", leader, cells),
+            };
+            chronicle.record(tick, text);
+            chronicle.leader = Some(leader);
+        }
+    }
+
+    for event in captured.read() {
+        if let Some(from) = event.from {
+            *chronicle.conquests.entry((event.to, from)).or_insert(0) += 1;
+        }
+    }
+    if (tick + 1).is_multiple_of(CONQUEST_WINDOW) {
+        let taken = std::mem::take(&mut chronicle.conquests);
+        let mut conquests: Vec<((EmpireId, EmpireId), u32)> = taken
+            .iter()
+            .map(|(&(to, from), &cells)| ((to, from), cells.saturating_sub(taken.get(&(from, to)).copied().unwrap_or(0))))
+            .filter(|(_, cells)| *cells >= CONQUEST_CELLS)
+            .collect();
+        conquests.sort_by_key(|((to, from), cells)| (std::cmp::Reverse(*cells), *to, *from));
+        for ((to, from), cells) in conquests {
+            chronicle.record(tick, format!("Empire {} conquered {} cells from Empire {} over the last {} ticks.", to, cells, from, CONQUEST_WINDOW));
+        }
+    }
+
+    for event in landed.read() {
+        if !chronicle.first_landing {
+            chronicle.first_landing = true;
+            let text = format!("The first boat makes it across the sea: Empire {} lands at ({}, {}).", event.empire, event.position.0, event.position.1);
+            chronicle.record(event.tick, text);
+        }
+    }
+
+    for event in invaded.read() {
+        let (landmass, from) = (landmasses.get(event.landmass), landmasses.get(event.from));
+        if landmass.cells.len() >= NOTABLE_LANDMASS {
+            let text = format!("Empire {} sails from {} and lands on {} at ({}, {}), the first invasion of the {}.", event.empire, from.name, landmass.name, event.position.0, event.position.1, landmass.kind());
+            chronicle.record(event.tick, text);
+        }
+    }
+
+    //every quarter of the way to max_tech is a milestone
+    for event in advanced.read() {
+        let before = ((event.tech - event.gain) / config.max_tech * 4.0).floor();
+        let after = (event.tech / config.max_tech * 4.0).floor();
+        if after > before && after >= 1.0 {
+            let text = format!("Empire {} reaches {}% of the maximum tech level.", event.empire, after as u32 * 25);
+            chronicle.record(event.tick, text);
+        }
+    }
+
+    if let Some(log) = &mut chronicle.log {
+        let _ = log.flush();
+    }
+}
+
+#[derive(Component)]
+pub struct ChronicleFeed;
+
+pub fn setup_feed(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle { font_size: 14.0, color: Color::WHITE, ..Default::default() })
+            .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.7))
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                max_width: Val::Px(480.0),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..Default::default()
+            }),
+        ChronicleFeed,
+    ));
+}
+
+//newest entries at the bottom, H hides the feed
+pub fn draw_feed_system(keyboard_input: Res<ButtonInput<KeyCode>>, mut chronicle: ResMut<Chronicle>, mut feed: Query<(&mut Text, &mut Style), With<ChronicleFeed>>) {
+    if keyboard_input.just_pressed(KeyCode::KeyH) {
+        chronicle.visible = !chronicle.visible;
+    }
+    let Ok((mut text, mut style)) = feed.get_single_mut() else {
+        return;
+    };
+    style.display = if chronicle.visible && !chronicle.feed.is_empty() { Display::Flex } else { Display::None };
+    if !chronicle.is_changed() {
+        return;
+    }
+    let skip = chronicle.feed.len().saturating_sub(FEED_LINES);
+    let lines: Vec<String> = chronicle.feed.iter().skip(skip).map(|(tick, entry)| format!("[{}] {}", tick, entry)).collect();
+    text.sections[0].value = format!("History (H to hide)\n{}", lines.join("\n"));
+}
@@ -0,0 +1,292 @@
+use crate::config::{ConfigError, SimConfig};
+use crate::RenderMode;
+use std::path::PathBuf;
+
+const USAGE: &str = "usage: empires [--config <file.toml|file.ron>] [--<config_key> <value>]...
+       [--seed <n>] [--headless [--ticks <n>]] [--heightmap <file.png>] [--owners <file.png>]
+       [--terrain <dir>] [--export-terrain <dir>]
+       [--load <file.sav>] [--save-at <tick> [--save-file <file.sav>]]
+       [--stats <file.csv|file.jsonl> [--stats-every <n>]] [--chronicle <file.log>]
+       [--record <file.replay>] [--rewind-every <n>] [--rewind-keep <n>]
+       [--export <dir> [--export-modes <mode,...|all>] [--export-every <n>] [--export-at <tick>]]
+       [--timelapse <dir|file.png> [--timelapse-every <n>] [--timelapse-mode <mode>] [--timelapse-scale <n>] [--timelapse-fps <n>]]
+       empires --replay <file.replay>";
+
+pub struct Args {
+    pub config: SimConfig,
+    pub headless: bool,
+    pub ticks: Option<u64>,
+    pub seed: u64,
+    pub load: Option<PathBuf>,
+    pub save_at: Option<u64>,
+    pub save_file: Option<PathBuf>,
+    pub stats: Option<PathBuf>,
+    pub stats_every: u64,
+    pub chronicle: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub heightmap: Option<PathBuf>,
+    pub owners: Option<PathBuf>,
+    pub terrain: Option<PathBuf>,
+    pub export_terrain: Option<PathBuf>,
+    pub rewind_every: u64,
+    pub rewind_keep: usize,
+    pub export: Option<PathBuf>,
+    pub export_modes: Vec<RenderMode>,
+    pub export_every: Option<u64>,
+    pub export_at: Option<u64>,
+    pub timelapse: Option<PathBuf>,
+    pub timelapse_every: u64,
+    pub timelapse_mode: RenderMode,
+    pub timelapse_scale: usize,
+    pub timelapse_fps: u16,
+}
+
+//a comma separated list of render mode names, or "all"
+fn parse_modes(value: &str) -> Result<Vec<RenderMode>, String> {
+    if value == "all" {
+        return Ok(RenderMode::ALL.to_vec());
+    }
+    value
+        .split(',')
+        .map(|name| {
+            RenderMode::from_name(name.trim()).ok_or_else(|| {
+                let names: Vec<&str> = RenderMode::ALL.iter().map(|mode| mode.name()).collect();
+                format!("unknown render mode '{}', expected one of {} or all", name, names.join(", "))
+            })
+        })
+        .collect()
+}
+
+//parse the command line. --config is applied first so individual flags always win over the file.
+pub fn parse(args: &[String]) -> Result<Args, String> {
+    let mut config_path: Option<PathBuf> = None;
+    let mut overrides = Vec::new();
+    let mut headless = false;
+    let mut ticks = None;
+    let mut seed = None;
+    let mut load = None;
+    let mut save_at = None;
+    let mut save_file = None;
+    let mut stats = None;
+    let mut stats_every = None;
+    let mut chronicle = None;
+    let mut record = None;
+    let mut replay = None;
+    let mut heightmap = None;
+    let mut owners = None;
+    let mut terrain = None;
+    let mut export_terrain = None;
+    let mut rewind_every = None;
+    let mut rewind_keep = None;
+    let mut export = None;
+    let mut export_modes = None;
+    let mut export_every = None;
+    let mut export_at = None;
+    let mut timelapse = None;
+    let mut timelapse_every = None;
+    let mut timelapse_mode = None;
+    let mut timelapse_scale = None;
+    let mut timelapse_fps = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument '{}'\n{}", arg, USAGE));
+        };
+        match flag {
+            "help" => return Err(USAGE.to_string()),
+            "headless" => headless = true,
+            _ => {
+                let value = iter.next().ok_or_else(|| format!("missing value for --{}\n{}", flag, USAGE))?;
+                match flag {
+                    "config" => config_path = Some(PathBuf::from(value)),
+                    "ticks" => ticks = Some(value.parse().map_err(|_| format!("invalid tick count '{}'", value))?),
+                    "seed" => seed = Some(value.parse().map_err(|_| format!("invalid seed '{}'", value))?),
+                    "load" => load = Some(PathBuf::from(value)),
+                    "save-at" => save_at = Some(value.parse().map_err(|_| format!("invalid tick '{}'", value))?),
+                    "save-file" => save_file = Some(PathBuf::from(value)),
+                    "stats" => stats = Some(PathBuf::from(value)),
+                    "chronicle" => chronicle = Some(PathBuf::from(value)),
+                    "record" => record = Some(PathBuf::from(value)),
+                    "replay" => replay = Some(PathBuf::from(value)),
+                    "stats-every" => stats_every = Some(value.parse().map_err(|_| format!("invalid tick count '{}'", value))?),
+                    "heightmap" => heightmap = Some(PathBuf::from(value)),
+                    "owners" => owners = Some(PathBuf::from(value)),
+                    "terrain" => terrain = Some(PathBuf::from(value)),
+                    "export-terrain" => export_terrain = Some(PathBuf::from(value)),
+                    "rewind-every" => rewind_every = Some(value.parse().map_err(|_| format!("invalid tick count '{}'", value))?),
+                    "export" => export = Some(PathBuf::from(value)),
+                    "export-modes" => export_modes = Some(parse_modes(value)?),
+                    "export-every" => export_every = Some(value.parse().map_err(|_| format!("invalid tick count '{}'", value))?),
+                    "export-at" => export_at = Some(value.parse().map_err(|_| format!("invalid tick '{}'", value))?),
+                    "timelapse" => timelapse = Some(PathBuf::from(value)),
+                    "timelapse-every" => timelapse_every = Some(value.parse().map_err(|_| format!("invalid tick count '{}'", value))?),
+                    "timelapse-mode" => timelapse_mode = Some(parse_modes(value)?),
+                    "timelapse-scale" => timelapse_scale = Some(value.parse().map_err(|_| format!("invalid scale '{}'", value))?),
+                    "timelapse-fps" => timelapse_fps = Some(value.parse().map_err(|_| format!("invalid frame rate '{}'", value))?),
+                    "rewind-keep" => rewind_keep = Some(value.parse().map_err(|_| format!("invalid snapshot count '{}'", value))?),
+                    _ => overrides.push((flag.replace('-', "_"), value.clone())),
+                }
+            }
+        }
+    }
+
+    //a save carries its own config and seed, mixing in new ones would make it a different run.
+    if load.is_some() && (config_path.is_some() || seed.is_some() || !overrides.is_empty() || heightmap.is_some() || owners.is_some() || terrain.is_some()) {
+        return Err(format!("--load can't be combined with --config, --seed, --heightmap, --owners, --terrain or config overrides\n{}", USAGE));
+    }
+    if terrain.is_some() && heightmap.is_some() {
+        return Err(format!("--terrain already has a heightmap, it can't be combined with --heightmap\n{}", USAGE));
+    }
+    if save_file.is_some() && save_at.is_none() {
+        return Err(format!("--save-file needs --save-at\n{}", USAGE));
+    }
+    //the world is only saved after a tick has run
+    if save_at == Some(0) {
+        return Err("--save-at must be at least 1".to_string());
+    }
+    //watching a replay doesn't run the simulation, so nothing that configures or records a run applies
+    let simulates = headless || config_path.is_some() || seed.is_some() || !overrides.is_empty() || load.is_some() || save_at.is_some() || heightmap.is_some() || owners.is_some() || terrain.is_some();
+    let observes = stats.is_some() || chronicle.is_some() || record.is_some() || rewind_every.is_some() || rewind_keep.is_some() || export.is_some() || timelapse.is_some();
+    if replay.is_some() && (simulates || observes) {
+        return Err(format!("--replay can't be combined with other options\n{}", USAGE));
+    }
+    if stats_every.is_some() && stats.is_none() {
+        return Err(format!("--stats-every needs --stats\n{}", USAGE));
+    }
+    if stats_every == Some(0) {
+        return Err("--stats-every must be at least 1".to_string());
+    }
+    //rewinding is done from the keyboard, there's nothing to press without a window
+    if headless && (rewind_every.is_some() || rewind_keep.is_some()) {
+        return Err(format!("--rewind-every and --rewind-keep don't apply to --headless runs\n{}", USAGE));
+    }
+    if rewind_every == Some(0) {
+        return Err("--rewind-every must be at least 1".to_string());
+    }
+    if export.is_none() && (export_modes.is_some() || export_every.is_some() || export_at.is_some()) {
+        return Err(format!("--export-modes, --export-every and --export-at need --export\n{}", USAGE));
+    }
+    if export.is_some() && export_every.is_none() && export_at.is_none() {
+        return Err(format!("--export needs --export-every or --export-at\n{}", USAGE));
+    }
+    if export_every == Some(0) {
+        return Err("--export-every must be at least 1".to_string());
+    }
+    if timelapse.is_none() && (timelapse_every.is_some() || timelapse_mode.is_some() || timelapse_scale.is_some() || timelapse_fps.is_some()) {
+        return Err(format!("--timelapse-every, --timelapse-mode, --timelapse-scale and --timelapse-fps need --timelapse\n{}", USAGE));
+    }
+    if timelapse_every == Some(0) || timelapse_scale == Some(0) || timelapse_fps == Some(0) {
+        return Err("--timelapse-every, --timelapse-scale and --timelapse-fps must be at least 1".to_string());
+    }
+    //a time-lapse is one view, for several use --export
+    let timelapse_mode = match timelapse_mode.as_deref() {
+        None => RenderMode::EmpireView,
+        Some([mode]) => *mode,
+        Some(_) => return Err("--timelapse-mode takes a single render mode".to_string()),
+    };
+
+    let mut config = match config_path {
+        Some(path) => SimConfig::load(&path).map_err(|e| e.to_string())?,
+        None => SimConfig::default(),
+    };
+    for (key, value) in overrides {
+        config.set(&key, &value).map_err(|e: ConfigError| e.to_string())?;
+    }
+    config.validate().map_err(|e| e.to_string())?;
+
+    if ticks.is_some() && !headless {
+        return Err(format!("--ticks only applies to --headless runs\n{}", USAGE));
+    }
+
+    //without a seed pick one at random, it's printed at startup so the run can still be reproduced.
+    let seed = seed.unwrap_or_else(rand::random);
+
+    Ok(Args {
+        config,
+        headless,
+        ticks,
+        seed,
+        load,
+        save_at,
+        save_file,
+        stats,
+        stats_every: stats_every.unwrap_or(10),
+        chronicle,
+        record,
+        replay,
+        heightmap,
+        owners,
+        terrain,
+        export_terrain,
+        //a snapshot of the default map is around 20MB, so ten of them is a sensible default
+        rewind_every: rewind_every.unwrap_or(100),
+        rewind_keep: rewind_keep.unwrap_or(10),
+        export,
+        export_modes: export_modes.unwrap_or(vec![RenderMode::EmpireView]),
+        export_every,
+        export_at,
+        timelapse,
+        timelapse_every: timelapse_every.unwrap_or(10),
+        timelapse_mode,
+        timelapse_scale: timelapse_scale.unwrap_or(1),
+        timelapse_fps: timelapse_fps.unwrap_or(15),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn error(line: &[&str]) -> String {
+        match parse(&args(line)) {
+            Ok(_) => panic!("{:?} should not parse", line),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn flags_and_overrides_are_applied() {
+        let parsed = parse(&args(&["--headless", "--ticks", "50", "--seed", "7", "--width", "64", "--ocean-cutoff", "0.4"])).unwrap();
+        assert!(parsed.headless);
+        assert_eq!(parsed.ticks, Some(50));
+        assert_eq!(parsed.seed, 7);
+        assert_eq!(parsed.config.width, 64);
+        assert_eq!(parsed.config.ocean_cutoff, 0.4);
+        assert_eq!(parsed.stats_every, 10);
+    }
+
+    #[test]
+    fn unknown_key_is_an_error() {
+        assert_eq!(error(&["--bogus", "1"]), "unknown config key 'bogus'");
+    }
+
+    #[test]
+    fn bad_values_are_errors() {
+        assert_eq!(error(&["--width", "wide"]), "invalid value 'wide' for 'width'");
+        assert_eq!(error(&["--seed", "abc"]), "invalid seed 'abc'");
+        assert!(error(&["--export", "out", "--export-at", "0", "--export-modes", "sideways"]).starts_with("unknown render mode 'sideways'"));
+        //values that parse but fail validation
+        assert!(error(&["--plates", "1"]).starts_with("plates is out of range"));
+    }
+
+    #[test]
+    fn missing_argument_is_an_error() {
+        assert!(error(&["--seed"]).starts_with("missing value for --seed\n"));
+        assert!(error(&["--headless", "--ticks"]).starts_with("missing value for --ticks\n"));
+        assert!(error(&["stray"]).starts_with("unexpected argument 'stray'\n"));
+    }
+
+    #[test]
+    fn conflicting_flags_are_errors() {
+        assert!(error(&["--load", "a.sav", "--seed", "1"]).starts_with("--load can't be combined"));
+        assert!(error(&["--ticks", "10"]).starts_with("--ticks only applies to --headless runs"));
+        assert!(error(&["--replay", "a.replay", "--headless"]).starts_with("--replay can't be combined"));
+        assert_eq!(error(&["--headless", "--save-at", "0"]), "--save-at must be at least 1");
+    }
+}
@@ -0,0 +1,327 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::hex::HexGrid;
+
+//past this the droplets have long since carved every valley they're going to, they only add to startup time
+const MAX_EROSION_PASSES: u32 = 64;
+
+//every tuning knob for a run. Loaded from a TOML or RON file and then overridden from the command line.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimConfig {
+    pub width: usize,
+    pub height: usize,
+    pub ocean_cutoff: f32,
+    pub empire_probability: i32,
+    pub terrain_need: f32,
+    pub terrain_strength: f32,
+    pub loop_dist: usize,
+    pub boat_prop: f32,
+    pub tech_gain: f32,
+    pub start_tech_range: f32,
+    pub min_boat_wait: u32,
+    pub max_tech: f32,
+    pub tech_decay: f32,
+    //world generation, see worldgen.rs
+    pub octaves: u32,
+    pub continent_scale: f32,
+    pub lacunarity: f32,
+    pub persistence: f32,
+    pub moisture_scale: f32,
+    //how much colder land gets from the coast to the highest peak
+    pub lapse_rate: f32,
+    //how much biomes change growth and need, 0 leaves them to elevation alone
+    pub climate_strength: f32,
+    pub generator: Generator,
+    //tectonic generator only, see tectonics.rs
+    pub plates: u32,
+    //each pass sends a droplet down from about every fourth cell
+    pub erosion_passes: u32,
+    //how many cells' worth of rain has to pass through a cell to make it a river, see rivers.rs
+    pub river_threshold: f32,
+    //how much more strength the largest rivers grow
+    pub river_growth: f32,
+    //how much more of a river cell's spare strength is sent on to reinforce its neighbors
+    pub river_speed: f32,
+    //bodies of water up to this many cells are lakes, which can't be sailed, and up to sea_size are seas
+    pub lake_size: usize,
+    pub sea_size: usize,
+    //how much more strength land on a lake shore grows
+    pub lake_growth: f32,
+    //landmasses of at least this many cells are continents, smaller ones are islands
+    pub continent_size: usize,
+}
+
+//how elevation is made. Noise uses the octaves above, tectonic drifts plates into each other and erodes the result.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Generator {
+    #[default]
+    Noise,
+    Tectonic,
+}
+
+impl std::str::FromStr for Generator {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "noise" => Ok(Generator::Noise),
+            "tectonic" => Ok(Generator::Tectonic),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            width: 16 * 30,
+            height: 9 * 30,
+            ocean_cutoff: 0.5,
+            empire_probability: 1,
+            terrain_need: 0.99,
+            terrain_strength: 0.7,
+            loop_dist: 10,
+            boat_prop: 0.01,
+            tech_gain: 0.001,
+            start_tech_range: 0.01,
+            min_boat_wait: 2,
+            max_tech: 0.2,
+            tech_decay: 0.000001,
+            octaves: 5,
+            continent_scale: 128.0,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            moisture_scale: 96.0,
+            lapse_rate: 0.6,
+            climate_strength: 0.5,
+            generator: Generator::Noise,
+            plates: 12,
+            erosion_passes: 4,
+            river_threshold: 100.0,
+            river_growth: 0.3,
+            river_speed: 0.5,
+            lake_size: 64,
+            sea_size: 2048,
+            lake_growth: 0.2,
+            continent_size: 2000,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(String, String),
+    UnknownKey(String),
+    BadValue(String, String),
+    OutOfRange(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "could not parse {}: {}", path, e),
+            ConfigError::UnknownKey(key) => write!(f, "unknown config key '{}'", key),
+            ConfigError::BadValue(key, value) => write!(f, "invalid value '{}' for '{}'", value, key),
+            ConfigError::OutOfRange(key, why) => write!(f, "{} is out of range: {}", key, why),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl SimConfig {
+    pub fn hex_grid(&self) -> HexGrid {
+        HexGrid::new(self.width, self.height)
+    }
+
+    //read a config file. The format is picked from the extension, anything that isn't .ron is treated as TOML.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let name = path.display().to_string();
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(name.clone(), e))?;
+        if path.extension().is_some_and(|ext| ext == "ron") {
+            ron::from_str(&text).map_err(|e| ConfigError::Parse(name, e.to_string()))
+        } else {
+            toml::from_str(&text).map_err(|e| ConfigError::Parse(name, e.to_string()))
+        }
+    }
+
+    //override a single field by name, used for --key value command line flags.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+            value.parse().map_err(|_| ConfigError::BadValue(key.to_string(), value.to_string()))
+        }
+        match key {
+            "width" => self.width = parse(key, value)?,
+            "height" => self.height = parse(key, value)?,
+            "ocean_cutoff" => self.ocean_cutoff = parse(key, value)?,
+            "empire_probability" => self.empire_probability = parse(key, value)?,
+            "terrain_need" => self.terrain_need = parse(key, value)?,
+            "terrain_strength" => self.terrain_strength = parse(key, value)?,
+            "loop_dist" => self.loop_dist = parse(key, value)?,
+            "boat_prop" => self.boat_prop = parse(key, value)?,
+            "tech_gain" => self.tech_gain = parse(key, value)?,
+            "start_tech_range" => self.start_tech_range = parse(key, value)?,
+            "min_boat_wait" => self.min_boat_wait = parse(key, value)?,
+            "max_tech" => self.max_tech = parse(key, value)?,
+            "tech_decay" => self.tech_decay = parse(key, value)?,
+            "octaves" => self.octaves = parse(key, value)?,
+            "continent_scale" => self.continent_scale = parse(key, value)?,
+            "lacunarity" => self.lacunarity = parse(key, value)?,
+            "persistence" => self.persistence = parse(key, value)?,
+            "moisture_scale" => self.moisture_scale = parse(key, value)?,
+            "lapse_rate" => self.lapse_rate = parse(key, value)?,
+            "climate_strength" => self.climate_strength = parse(key, value)?,
+            "generator" => self.generator = parse(key, value)?,
+            "plates" => self.plates = parse(key, value)?,
+            "erosion_passes" => self.erosion_passes = parse(key, value)?,
+            "river_threshold" => self.river_threshold = parse(key, value)?,
+            "river_growth" => self.river_growth = parse(key, value)?,
+            "river_speed" => self.river_speed = parse(key, value)?,
+            "lake_size" => self.lake_size = parse(key, value)?,
+            "sea_size" => self.sea_size = parse(key, value)?,
+            "lake_growth" => self.lake_growth = parse(key, value)?,
+            "continent_size" => self.continent_size = parse(key, value)?,
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    //reject values that would panic or make the simulation meaningless.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        fn unit(key: &'static str, value: f32) -> Result<(), ConfigError> {
+            if (0.0..=1.0).contains(&value) {
+                Ok(())
+            } else {
+                Err(ConfigError::OutOfRange(key, format!("{} is not between 0 and 1", value)))
+            }
+        }
+        if self.width < 2 || self.height < 2 {
+            return Err(ConfigError::OutOfRange("width/height", format!("{}x{} is too small", self.width, self.height)));
+        }
+        if self.loop_dist * 2 >= self.width {
+            return Err(ConfigError::OutOfRange("loop_dist", format!("{} must be less than half the width ({})", self.loop_dist, self.width)));
+        }
+        if !(0.0..1.0).contains(&self.ocean_cutoff) {
+            return Err(ConfigError::OutOfRange("ocean_cutoff", format!("{} is not in [0, 1)", self.ocean_cutoff)));
+        }
+        if self.empire_probability < 1 {
+            return Err(ConfigError::OutOfRange("empire_probability", format!("{} must be at least 1", self.empire_probability)));
+        }
+        unit("terrain_need", self.terrain_need)?;
+        unit("terrain_strength", self.terrain_strength)?;
+        if self.boat_prop <= 0.0 || !self.boat_prop.is_finite() {
+            return Err(ConfigError::OutOfRange("boat_prop", format!("{} must be positive", self.boat_prop)));
+        }
+        unit("tech_gain", self.tech_gain)?;
+        if self.start_tech_range <= 0.0 || self.start_tech_range > 1.0 {
+            return Err(ConfigError::OutOfRange("start_tech_range", format!("{} is not in (0, 1]", self.start_tech_range)));
+        }
+        unit("max_tech", self.max_tech)?;
+        unit("tech_decay", self.tech_decay)?;
+        if self.octaves < 1 {
+            return Err(ConfigError::OutOfRange("octaves", "there has to be at least one octave".to_string()));
+        }
+        for (key, scale) in [("continent_scale", self.continent_scale), ("moisture_scale", self.moisture_scale)] {
+            if scale < 1.0 || !scale.is_finite() {
+                return Err(ConfigError::OutOfRange(key, format!("{} must be at least 1 cell", scale)));
+            }
+        }
+        if self.lacunarity <= 1.0 || !self.lacunarity.is_finite() {
+            return Err(ConfigError::OutOfRange("lacunarity", format!("{} must be greater than 1", self.lacunarity)));
+        }
+        if self.persistence <= 0.0 || self.persistence > 1.0 {
+            return Err(ConfigError::OutOfRange("persistence", format!("{} is not in (0, 1]", self.persistence)));
+        }
+        unit("lapse_rate", self.lapse_rate)?;
+        unit("climate_strength", self.climate_strength)?;
+        if self.plates < 2 {
+            return Err(ConfigError::OutOfRange("plates", format!("{} must be at least 2, one plate has nothing to collide with", self.plates)));
+        }
+        if self.river_threshold <= 0.0 || !self.river_threshold.is_finite() {
+            return Err(ConfigError::OutOfRange("river_threshold", format!("{} must be positive", self.river_threshold)));
+        }
+        unit("river_growth", self.river_growth)?;
+        unit("river_speed", self.river_speed)?;
+        if self.erosion_passes > MAX_EROSION_PASSES {
+            return Err(ConfigError::OutOfRange("erosion_passes", format!("{} is more than {}", self.erosion_passes, MAX_EROSION_PASSES)));
+        }
+        //lakes and seas can be switched off with 0, but a body bigger than the whole map can never exist
+        let cells = self.width * self.height;
+        if self.lake_size > cells {
+            return Err(ConfigError::OutOfRange("lake_size", format!("{} is more than the {} cells on the map", self.lake_size, cells)));
+        }
+        if self.sea_size < self.lake_size {
+            return Err(ConfigError::OutOfRange("sea_size", format!("{} is smaller than lake_size ({})", self.sea_size, self.lake_size)));
+        }
+        if self.sea_size > cells {
+            return Err(ConfigError::OutOfRange("sea_size", format!("{} is more than the {} cells on the map", self.sea_size, cells)));
+        }
+        if self.continent_size < 1 || self.continent_size > cells {
+            return Err(ConfigError::OutOfRange("continent_size", format!("{} is not between 1 and the {} cells on the map", self.continent_size, cells)));
+        }
+        unit("lake_growth", self.lake_growth)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_overrides_fields_by_name() {
+        let mut config = SimConfig::default();
+        config.set("width", "64").unwrap();
+        config.set("ocean_cutoff", "0.4").unwrap();
+        config.set("generator", "tectonic").unwrap();
+        assert_eq!(config.width, 64);
+        assert_eq!(config.ocean_cutoff, 0.4);
+        assert_eq!(config.generator, Generator::Tectonic);
+    }
+
+    #[test]
+    fn set_rejects_unknown_keys_and_bad_values() {
+        let mut config = SimConfig::default();
+        assert!(matches!(config.set("bogus", "1"), Err(ConfigError::UnknownKey(key)) if key == "bogus"));
+        assert!(matches!(config.set("width", "wide"), Err(ConfigError::BadValue(key, value)) if key == "width" && value == "wide"));
+        assert!(matches!(config.set("generator", "volcanic"), Err(ConfigError::BadValue(..))));
+        //a failed set leaves the field alone
+        assert_eq!(config.width, SimConfig::default().width);
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        SimConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_values() {
+        let out_of_range = |key: &str, value: &str| {
+            let mut config = SimConfig::default();
+            config.set(key, value).unwrap();
+            match config.validate() {
+                Err(ConfigError::OutOfRange(..)) => {}
+                other => panic!("{} = {} gave {:?}", key, value, other),
+            }
+        };
+        out_of_range("width", "1");
+        out_of_range("loop_dist", "240");
+        out_of_range("ocean_cutoff", "1");
+        out_of_range("terrain_need", "1.5");
+        out_of_range("plates", "1");
+        out_of_range("erosion_passes", "65");
+        out_of_range("lake_size", "1000000");
+        out_of_range("sea_size", "10");
+        out_of_range("sea_size", "1000000");
+        out_of_range("continent_size", "0");
+        out_of_range("continent_size", "1000000");
+    }
+}
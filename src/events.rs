@@ -0,0 +1,185 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::landmass::LandmassId;
+use crate::map::{EmpireId, MapData};
+
+//things that happen during a tick, sent from the systems that apply the rules so stats, UI, logs and
+//replays can follow along without reaching into push/pull. Every event carries the tick it happened on.
+//Events describe what happened in full even where no subscriber reads a field yet, those are allowed to be dead code.
+
+//a new empire appeared, currently only when the world is generated
+#[derive(Event, Clone, Copy, Debug)]
+#[allow(dead_code)]
+pub struct EmpireFounded {
+    pub tick: u64,
+    pub empire: EmpireId,
+    pub position: (usize, usize),
+}
+
+//a cell changed hands in pull, from is None when it was unowned
+#[derive(Event, Clone, Copy, Debug)]
+#[allow(dead_code)]
+pub struct CellCaptured {
+    pub tick: u64,
+    pub position: (usize, usize),
+    pub from: Option<EmpireId>,
+    pub to: EmpireId,
+}
+
+//a cell with no friendly neighbors fell apart in push and is now unowned
+#[derive(Event, Clone, Copy, Debug)]
+#[allow(dead_code)]
+pub struct CellAbandoned {
+    pub tick: u64,
+    pub position: (usize, usize),
+    pub empire: EmpireId,
+}
+
+//an empire lost its last cell
+#[derive(Event, Clone, Copy, Debug)]
+pub struct EmpireExtinct {
+    pub tick: u64,
+    pub empire: EmpireId,
+    //the cell it lost last
+    pub position: (usize, usize),
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+#[allow(dead_code)]
+pub struct BoatLaunched {
+    pub tick: u64,
+    pub boat: u64,
+    pub empire: EmpireId,
+    pub from: (usize, usize),
+    pub strength: f32,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+#[allow(dead_code)]
+pub struct BoatLanded {
+    pub tick: u64,
+    pub boat: u64,
+    pub empire: EmpireId,
+    pub position: (usize, usize),
+    pub strength: f32,
+}
+
+//a boat from another landmass came ashore on a landmass for the first time
+#[derive(Event, Clone, Copy, Debug)]
+pub struct LandmassInvaded {
+    pub tick: u64,
+    pub landmass: LandmassId,
+    pub from: LandmassId,
+    pub empire: EmpireId,
+    pub position: (usize, usize),
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TechAdvanced {
+    pub tick: u64,
+    pub empire: EmpireId,
+    pub gain: f32,
+    pub tech: f32,
+}
+
+//cells owned by each empire, kept up to date from ownership changes so extinction doesn't need a recount
+#[derive(Resource, Default)]
+pub struct Territory(pub Vec<u32>);
+
+impl Territory {
+    //count a cell leaving an empire, true if that was its last one. An empire that isn't counted as owning anything can't lose it.
+    pub fn lose(&mut self, empire: EmpireId) -> bool {
+        let Some(cells) = self.0.get_mut(empire.index()) else {
+            return false;
+        };
+        match cells.checked_sub(1) {
+            Some(left) => {
+                *cells = left;
+                left == 0
+            }
+            None => false,
+        }
+    }
+
+    pub fn gain(&mut self, empire: EmpireId) {
+        if self.0.len() <= empire.index() {
+            self.0.resize(empire.index() + 1, 0);
+        }
+        self.0[empire.index()] += 1;
+    }
+}
+
+//everything needed to report cells changing owner, used by both push and pull
+#[derive(SystemParam)]
+pub struct OwnershipEvents<'w> {
+    territory: ResMut<'w, Territory>,
+    captured: EventWriter<'w, CellCaptured>,
+    abandoned: EventWriter<'w, CellAbandoned>,
+    extinct: EventWriter<'w, EmpireExtinct>,
+}
+
+impl OwnershipEvents<'_> {
+    //changes are (index, old owner, new owner), in index order so events come out the same way every run
+    pub fn send(&mut self, tick: u64, width: usize, changes: Vec<(usize, Option<EmpireId>, Option<EmpireId>)>) {
+        for (index, from, to) in changes {
+            let position = (index % width, index / width);
+            match to {
+                Some(to) => {
+                    self.territory.gain(to);
+                    self.captured.send(CellCaptured { tick, position, from, to });
+                }
+                None => {
+                    if let Some(empire) = from {
+                        self.abandoned.send(CellAbandoned { tick, position, empire });
+                    }
+                }
+            }
+            if let Some(from) = from {
+                if self.territory.lose(from) {
+                    self.extinct.send(EmpireExtinct { tick, empire: from, position });
+                }
+            }
+        }
+    }
+}
+
+pub fn add_events(app: &mut App) {
+    app.add_event::<EmpireFounded>()
+        .add_event::<CellCaptured>()
+        .add_event::<CellAbandoned>()
+        .add_event::<EmpireExtinct>()
+        .add_event::<BoatLaunched>()
+        .add_event::<BoatLanded>()
+        .add_event::<LandmassInvaded>()
+        .add_event::<TechAdvanced>()
+        .init_resource::<Territory>()
+        .add_systems(PostStartup, count_territory_system);
+}
+
+//the world is built (or loaded) during Startup, count what everyone owns once it exists
+pub fn count_territory_system(cell_map: Res<MapData>, mut territory: ResMut<Territory>) {
+    territory.0 = vec![0; cell_map.empires.len()];
+    for empire in cell_map.front.empire.iter().flatten() {
+        territory.gain(*empire);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn territory_counts_down_to_extinction() {
+        let mut territory = Territory::default();
+        territory.gain(EmpireId(1));
+        territory.gain(EmpireId(1));
+        assert!(!territory.lose(EmpireId(1)));
+        assert!(territory.lose(EmpireId(1)));
+        //already empty, or never counted at all
+        assert!(!territory.lose(EmpireId(1)));
+        assert!(!territory.lose(EmpireId(0)));
+        assert!(!territory.lose(EmpireId(7)));
+        assert_eq!(territory.0, vec![0, 0]);
+    }
+}
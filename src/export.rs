@@ -0,0 +1,111 @@
+use bevy::prelude::*;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::config::SimConfig;
+use crate::map::MapData;
+use crate::render::map_colors;
+use crate::rng::WorldSeed;
+use crate::{GameData, Grid, RenderMode};
+
+//the map drawn on the CPU and written to PNG, so runs without a window (or a GPU) can still be looked at.
+//Images are laid out like the map on screen: two pixels per cell, odd rows shifted right by one and wrapping around.
+pub fn map_pixels(colors: &[[u8; 4]], width: usize, height: usize) -> Vec<u8> {
+    let mut pixels = vec![0; 2 * width * height * 3];
+    for (index, color) in colors.iter().enumerate() {
+        let (x, y) = (index % width, index / width);
+        //images are stored top row first, but y goes up on screen
+        let row = (height - 1 - y) * 2 * width;
+        let left = 2 * x + y % 2;
+        for pixel in [left, (left + 1) % (2 * width)] {
+            let offset = (row + pixel) * 3;
+            pixels[offset..offset + 3].copy_from_slice(&color[..3]);
+        }
+    }
+    pixels
+}
+
+//blow the image up by a whole number, each pixel becomes a scale x scale square
+pub fn scale_pixels(pixels: &[u8], width: usize, scale: usize) -> Vec<u8> {
+    if scale == 1 {
+        return pixels.to_vec();
+    }
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+    for row in pixels.chunks(width * 3) {
+        let start = scaled.len();
+        for pixel in row.chunks(3) {
+            for _ in 0..scale {
+                scaled.extend_from_slice(pixel);
+            }
+        }
+        for _ in 1..scale {
+            scaled.extend_from_within(start..start + width * scale * 3);
+        }
+    }
+    scaled
+}
+
+pub fn write_png(path: &Path, pixels: &[u8], width: usize, height: usize) -> Result<(), png::EncodingError> {
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()
+}
+
+//one render mode of the world as it is now
+pub fn write_map(path: &Path, render_mode: RenderMode, grid: &Grid, map: &MapData, game_data: &GameData, config: &SimConfig) -> Result<(), png::EncodingError> {
+    let colors = map_colors(render_mode, grid, map, game_data, config, None);
+    let pixels = map_pixels(&colors, config.width, config.height);
+    write_png(path, &pixels, 2 * config.width, config.height)
+}
+
+//images written on a schedule, from --export
+#[derive(Resource)]
+pub struct MapExport {
+    pub dir: PathBuf,
+    pub modes: Vec<RenderMode>,
+    pub every: Option<u64>,
+    pub at: Option<u64>,
+}
+
+//runs at the end of every tick when --export is given, and once at startup so tick 0 can be exported too
+pub fn export_system(export: Res<MapExport>, grid: Res<Grid>, map: Res<MapData>, game_data: Res<GameData>, config: Res<SimConfig>) {
+    let tick = game_data.tick;
+    if export.at != Some(tick) && !export.every.is_some_and(|every| tick.is_multiple_of(every)) {
+        return;
+    }
+    for mode in &export.modes {
+        let path = export.dir.join(format!("t{:06}-{}.png", tick, mode.name()));
+        if let Err(e) = write_map(&path, *mode, &grid, &map, &game_data, &config) {
+            eprintln!("Could not export {}: {}", path.display(), e);
+        }
+    }
+}
+
+//F12 writes whatever render mode is on screen
+pub fn screenshot_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    render_mode: Res<RenderMode>,
+    seed: Res<WorldSeed>,
+    grid: Res<Grid>,
+    map: Res<MapData>,
+    game_data: Res<GameData>,
+    config: Res<SimConfig>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F12) {
+        return;
+    }
+    let path = PathBuf::from(format!("screenshots/empires-{}-t{}-{}.png", seed.0, game_data.tick, render_mode.name()));
+    match write_map(&path, *render_mode, &grid, &map, &game_data, &config) {
+        Ok(()) => println!("Saved a screenshot of tick {} to {}", game_data.tick, path.display()),
+        Err(e) => eprintln!("Could not save {}: {}", path.display(), e),
+    }
+}
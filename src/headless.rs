@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::time::Instant;
+
+use crate::landmass::Landmasses;
+use crate::map::{EmpireId, MapData};
+use crate::rng::WorldSeed;
+use crate::GameData;
+
+//landmasses smaller than this are left out of the summary
+const REPORT_CELLS: usize = 100;
+
+//tracks a run with no window. It stops after max_ticks, or once at most one empire is left if no limit was given.
+//ticks are counted from start_tick so a loaded save runs for max_ticks more.
+#[derive(Resource)]
+pub struct HeadlessRun {
+    pub max_ticks: Option<u64>,
+    pub start_tick: u64,
+    pub start: Instant,
+}
+
+impl HeadlessRun {
+    pub fn new(max_ticks: Option<u64>, start_tick: u64) -> Self {
+        HeadlessRun { max_ticks, start_tick, start: Instant::now() }
+    }
+}
+
+pub fn headless_progress_system(run: Res<HeadlessRun>, seed: Res<WorldSeed>, game_data: Res<GameData>, cell_map: Res<MapData>, landmasses: Res<Landmasses>, mut exit: EventWriter<AppExit>) {
+    let mut territory: HashMap<EmpireId, usize> = HashMap::default();
+    for empire in cell_map.front.empire.iter().flatten() {
+        *territory.entry(*empire).or_insert(0) += 1;
+    }
+
+    let ticks_run = game_data.tick - run.start_tick;
+    let out_of_ticks = run.max_ticks.is_some_and(|max| ticks_run >= max);
+    let decided = territory.len() <= 1;
+    if !out_of_ticks && !decided {
+        return;
+    }
+
+    let elapsed = run.start.elapsed();
+    println!("---- Simulation summary ----");
+    println!("Seed:\t\t{}", seed.0);
+    println!("Ticks run:\t{} (now at tick {})", ticks_run, game_data.tick);
+    println!("Elapsed:\t{:.2?} ({:.1} ticks/s)", elapsed, ticks_run as f64 / elapsed.as_secs_f64().max(f64::EPSILON));
+    println!("Empires left:\t{} of {}", territory.len(), cell_map.empires.len());
+    match territory.len() {
+        0 => println!("Every empire has collapsed."),
+        1 => println!("Empire {} controls the world.", territory.keys().next().unwrap()),
+        _ => println!("Stopped after reaching the tick limit."),
+    }
+
+    let mut ranking: Vec<(EmpireId, usize)> = territory.into_iter().collect();
+    ranking.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    for (empire, cells) in ranking.iter().take(10) {
+        let tech = cell_map.empire(*empire).tech;
+        println!("  Empire {}\t{} cells\ttech {:.5}", empire, cells, tech);
+    }
+    println!("Landmasses:");
+    for line in landmasses.report(&cell_map, REPORT_CELLS).iter().take(10) {
+        println!("{}", line);
+    }
+    exit.send(AppExit::Success);
+}
@@ -0,0 +1,407 @@
+//hex grid geometry shared by every system.
+//cells are stored in "odd-r" offset coordinates: odd rows are drawn shifted right by half a cell.
+//y grows upward on screen, so y + 1 is north. The map wraps around horizontally but not vertically.
+//some helpers (rings, lines, distances) are here for tools and tests rather than the core rules, those are marked as allowed to be unused.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+//the six neighbor directions, counterclockwise starting from east. Each is 60 degrees from the last.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Direction {
+    East,
+    NorthEast,
+    NorthWest,
+    West,
+    SouthWest,
+    SouthEast,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::East,
+        Direction::NorthEast,
+        Direction::NorthWest,
+        Direction::West,
+        Direction::SouthWest,
+        Direction::SouthEast,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_index(index: usize) -> Direction {
+        Direction::ALL[index % 6]
+    }
+
+    //axial step for this direction
+    pub fn delta(self) -> Axial {
+        match self {
+            Direction::East => Axial::new(1, 0),
+            Direction::NorthEast => Axial::new(0, 1),
+            Direction::NorthWest => Axial::new(-1, 1),
+            Direction::West => Axial::new(-1, 0),
+            Direction::SouthWest => Axial::new(0, -1),
+            Direction::SouthEast => Axial::new(1, -1),
+        }
+    }
+
+    pub fn rotate_left(self) -> Direction {
+        Direction::from_index(self.index() + 1)
+    }
+
+    pub fn rotate_right(self) -> Direction {
+        Direction::from_index(self.index() + 5)
+    }
+
+    #[allow(dead_code)]
+    pub fn opposite(self) -> Direction {
+        Direction::from_index(self.index() + 3)
+    }
+
+    //bounce off the top or bottom edge of the map
+    pub fn mirror_vertical(self) -> Direction {
+        match self {
+            Direction::East => Direction::East,
+            Direction::NorthEast => Direction::SouthEast,
+            Direction::NorthWest => Direction::SouthWest,
+            Direction::West => Direction::West,
+            Direction::SouthWest => Direction::NorthWest,
+            Direction::SouthEast => Direction::NorthEast,
+        }
+    }
+
+    //screen angle in degrees, 0 = east, counterclockwise
+    pub fn angle(self) -> f32 {
+        self.index() as f32 * 60.0
+    }
+}
+
+//odd-r offset coordinate, the layout cells are stored in. Signed so it can step off the map before wrapping.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct Offset {
+    pub x: i32,
+    pub y: i32,
+}
+
+//axial coordinate, where hex math is simple. r is the row, q is slanted so each direction is a fixed step.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Axial {
+    pub q: i32,
+    pub r: i32,
+}
+
+impl Offset {
+    pub fn new(x: i32, y: i32) -> Self {
+        Offset { x, y }
+    }
+
+    pub fn to_axial(self) -> Axial {
+        Axial::new(self.x - (self.y - (self.y & 1)) / 2, self.y)
+    }
+
+    //the neighbor one step away, ignoring map bounds
+    pub fn step(self, direction: Direction) -> Offset {
+        (self.to_axial() + direction.delta()).to_offset()
+    }
+}
+
+impl From<(usize, usize)> for Offset {
+    fn from(position: (usize, usize)) -> Self {
+        Offset::new(position.0 as i32, position.1 as i32)
+    }
+}
+
+impl Axial {
+    pub fn new(q: i32, r: i32) -> Self {
+        Axial { q, r }
+    }
+
+    pub fn to_offset(self) -> Offset {
+        Offset::new(self.q + (self.r - (self.r & 1)) / 2, self.r)
+    }
+
+    #[allow(dead_code)]
+    pub fn length(self) -> i32 {
+        (self.q.abs() + self.r.abs() + (self.q + self.r).abs()) / 2
+    }
+
+    #[allow(dead_code)]
+    pub fn distance(self, other: Axial) -> i32 {
+        (self - other).length()
+    }
+}
+
+impl std::ops::Add for Axial {
+    type Output = Axial;
+    fn add(self, other: Axial) -> Axial {
+        Axial::new(self.q + other.q, self.r + other.r)
+    }
+}
+
+impl std::ops::Sub for Axial {
+    type Output = Axial;
+    fn sub(self, other: Axial) -> Axial {
+        Axial::new(self.q - other.q, self.r - other.r)
+    }
+}
+
+impl std::ops::Mul<i32> for Axial {
+    type Output = Axial;
+    fn mul(self, k: i32) -> Axial {
+        Axial::new(self.q * k, self.r * k)
+    }
+}
+
+//the map's shape. Knows how to wrap x and where the top and bottom edges are.
+#[derive(Clone, Copy, Debug)]
+pub struct HexGrid {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl HexGrid {
+    pub fn new(width: usize, height: usize) -> Self {
+        HexGrid { width, height }
+    }
+
+    //wrap x around the map, None if y is off the top or bottom
+    pub fn wrap(&self, offset: Offset) -> Option<(usize, usize)> {
+        if offset.y < 0 || offset.y >= self.height as i32 {
+            return None;
+        }
+        Some((offset.x.rem_euclid(self.width as i32) as usize, offset.y as usize))
+    }
+
+    pub fn neighbor(&self, position: (usize, usize), direction: Direction) -> Option<(usize, usize)> {
+        self.wrap(Offset::from(position).step(direction))
+    }
+
+    //neighbors in Direction::ALL order, skipping the ones off the top or bottom of the map
+    pub fn neighbors(&self, position: (usize, usize)) -> impl Iterator<Item = (Direction, (usize, usize))> + '_ {
+        Direction::ALL.into_iter().filter_map(move |direction| self.neighbor(position, direction).map(|n| (direction, n)))
+    }
+
+    //which way to step from `from` to reach the adjacent cell `to`, None if they aren't neighbors
+    pub fn direction_between(&self, from: (usize, usize), to: (usize, usize)) -> Option<Direction> {
+        Direction::ALL.into_iter().find(|direction| self.neighbor(from, *direction) == Some(to))
+    }
+
+    //the copy of `to` (shifted by a map width or not) closest to `from`, in axial coordinates
+    #[allow(dead_code)]
+    fn nearest_image(&self, from: Axial, to: (usize, usize)) -> Axial {
+        let to = Offset::from(to).to_axial();
+        let width = self.width as i32;
+        [to, to + Axial::new(width, 0), to - Axial::new(width, 0)]
+            .into_iter()
+            .min_by_key(|image| from.distance(*image))
+            .unwrap()
+    }
+
+    //number of steps between two cells, going around the horizontal seam if that's shorter
+    #[allow(dead_code)]
+    pub fn distance(&self, a: (usize, usize), b: (usize, usize)) -> i32 {
+        let a = Offset::from(a).to_axial();
+        a.distance(self.nearest_image(a, b))
+    }
+
+    //every cell exactly `radius` steps from center, clipped to the map. Radius 0 is the center itself.
+    #[allow(dead_code)]
+    pub fn ring(&self, center: (usize, usize), radius: u32) -> Vec<(usize, usize)> {
+        let center_axial = Offset::from(center).to_axial();
+        if radius == 0 {
+            return vec![center];
+        }
+        let radius = radius as i32;
+        let mut cells = Vec::with_capacity(6 * radius as usize);
+        let mut hex = center_axial + Direction::SouthWest.delta() * radius;
+        for direction in Direction::ALL {
+            for _ in 0..radius {
+                if let Some(cell) = self.wrap(hex.to_offset()) {
+                    cells.push(cell);
+                }
+                hex = hex + direction.delta();
+            }
+        }
+        cells
+    }
+
+    //cells on the straight line from a to b, both ends included, each step adjacent to the last
+    #[allow(dead_code)]
+    pub fn line(&self, a: (usize, usize), b: (usize, usize)) -> Vec<(usize, usize)> {
+        let start = Offset::from(a).to_axial();
+        let end = self.nearest_image(start, b);
+        let steps = start.distance(end);
+        let mut cells = Vec::with_capacity(steps as usize + 1);
+        for i in 0..=steps {
+            let t = if steps == 0 { 0.0 } else { i as f32 / steps as f32 };
+            //nudge off exact ties so lines don't zigzag
+            let q = start.q as f32 + (end.q - start.q) as f32 * t + 1e-4;
+            let r = start.r as f32 + (end.r - start.r) as f32 * t + 1e-4;
+            if let Some(cell) = self.wrap(axial_round(q, r).to_offset()) {
+                cells.push(cell);
+            }
+        }
+        cells
+    }
+
+    //center of a cell in world units, matching where its sprite is drawn
+    pub fn world_position(&self, position: (usize, usize)) -> Vec2 {
+        let offset = (position.1 % 2) as f32 / 2.0;
+        Vec2::new(position.0 as f32 + offset, position.1 as f32)
+    }
+
+    //the cell under a world position, None if it's above or below the map
+    pub fn cell_at(&self, world: Vec2) -> Option<(usize, usize)> {
+        let y = world.y.round() as i32;
+        let offset = (y & 1) as f32 / 2.0;
+        let x = (world.x - offset).round() as i32;
+        self.wrap(Offset::new(x, y))
+    }
+}
+
+#[allow(dead_code)]
+fn axial_round(q: f32, r: f32) -> Axial {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    Axial::new(rq as i32, rr as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: HexGrid = HexGrid { width: 12, height: 9 };
+
+    fn all_cells() -> impl Iterator<Item = (usize, usize)> {
+        (0..GRID.height).flat_map(|y| (0..GRID.width).map(move |x| (x, y)))
+    }
+
+    #[test]
+    fn offset_axial_round_trip() {
+        for y in -5..5 {
+            for x in -5..5 {
+                let offset = Offset::new(x, y);
+                assert_eq!(offset.to_axial().to_offset(), offset);
+            }
+        }
+    }
+
+    #[test]
+    fn neighbor_tables_match_row_parity() {
+        //the odd-r layout: even rows reach up/down to x-1 and x, odd rows to x and x+1.
+        let even: Vec<_> = GRID.neighbors((4, 4)).map(|(_, n)| n).collect();
+        assert_eq!(even, vec![(5, 4), (4, 5), (3, 5), (3, 4), (3, 3), (4, 3)]);
+        let odd: Vec<_> = GRID.neighbors((4, 5)).map(|(_, n)| n).collect();
+        assert_eq!(odd, vec![(5, 5), (5, 6), (4, 6), (3, 5), (4, 4), (5, 4)]);
+    }
+
+    #[test]
+    fn neighbors_are_mutual() {
+        for cell in all_cells() {
+            for (direction, neighbor) in GRID.neighbors(cell) {
+                assert_eq!(GRID.neighbor(neighbor, direction.opposite()), Some(cell), "{:?} {:?}", cell, direction);
+                assert!(GRID.neighbors(neighbor).any(|(_, n)| n == cell));
+            }
+        }
+    }
+
+    #[test]
+    fn edge_rows_have_fewer_neighbors_and_x_wraps() {
+        assert_eq!(GRID.neighbors((3, 0)).count(), 4);
+        assert_eq!(GRID.neighbors((3, GRID.height - 1)).count(), 4);
+        assert_eq!(GRID.neighbors((3, 4)).count(), 6);
+        assert_eq!(GRID.neighbor((0, 4), Direction::West), Some((GRID.width - 1, 4)));
+        assert_eq!(GRID.neighbor((GRID.width - 1, 3), Direction::NorthEast), Some((0, 4)));
+    }
+
+    #[test]
+    fn direction_between_inverts_neighbor() {
+        for cell in all_cells() {
+            for (direction, neighbor) in GRID.neighbors(cell) {
+                assert_eq!(GRID.direction_between(cell, neighbor), Some(direction));
+            }
+        }
+        assert_eq!(GRID.direction_between((2, 2), (6, 6)), None);
+    }
+
+    #[test]
+    fn directions_point_the_way_they_are_named() {
+        //the screen position of every neighbor should sit at the direction's angle
+        for cell in [(4, 4), (4, 5)] {
+            let center = GRID.world_position(cell);
+            for (direction, neighbor) in GRID.neighbors(cell) {
+                let delta = GRID.world_position(neighbor) - center;
+                let angle = delta.y.atan2(delta.x).to_degrees().rem_euclid(360.0);
+                //rows are 1 apart and columns 1 apart, so diagonals sit near 63 degrees rather than 60
+                let expected = direction.angle();
+                let diff = (angle - expected + 180.0).rem_euclid(360.0) - 180.0;
+                assert!(diff.abs() < 5.0, "{:?} from {:?}: {} vs {}", direction, cell, angle, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn rotations_and_mirrors_are_consistent() {
+        for direction in Direction::ALL {
+            assert_eq!(direction.rotate_left().rotate_right(), direction);
+            assert_eq!(direction.opposite().opposite(), direction);
+            assert_eq!(direction.mirror_vertical().mirror_vertical(), direction);
+            let step = GRID.world_position(GRID.neighbor((4, 4), direction).unwrap()) - GRID.world_position((4, 4));
+            let mirrored = GRID.world_position(GRID.neighbor((4, 4), direction.mirror_vertical()).unwrap()) - GRID.world_position((4, 4));
+            assert_eq!(step.x, mirrored.x);
+            assert_eq!(step.y, -mirrored.y);
+        }
+    }
+
+    #[test]
+    fn distance_counts_steps_and_wraps() {
+        for cell in all_cells() {
+            assert_eq!(GRID.distance(cell, cell), 0);
+            for (_, neighbor) in GRID.neighbors(cell) {
+                assert_eq!(GRID.distance(cell, neighbor), 1);
+            }
+        }
+        assert_eq!(GRID.distance((0, 4), (GRID.width - 1, 4)), 1);
+        assert_eq!(GRID.distance((0, 0), (0, 8)), 8);
+    }
+
+    #[test]
+    fn rings_have_the_right_size_and_distance() {
+        let big = HexGrid::new(40, 40);
+        for radius in 0..5 {
+            let ring = big.ring((20, 20), radius);
+            assert_eq!(ring.len(), if radius == 0 { 1 } else { 6 * radius as usize });
+            assert!(ring.iter().all(|cell| big.distance((20, 20), *cell) == radius as i32));
+        }
+        assert_eq!(big.ring((20, 20), 1).len(), big.neighbors((20, 20)).count());
+    }
+
+    #[test]
+    fn lines_are_connected() {
+        let big = HexGrid::new(40, 40);
+        for (a, b) in [((2, 3), (17, 11)), ((5, 30), (5, 2)), ((1, 10), (38, 12)), ((7, 7), (7, 7))] {
+            let line = big.line(a, b);
+            assert_eq!(line.first(), Some(&a));
+            assert_eq!(line.last(), Some(&b));
+            assert_eq!(line.len() as i32, big.distance(a, b) + 1);
+            for pair in line.windows(2) {
+                assert_eq!(big.distance(pair[0], pair[1]), 1, "{:?}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn world_position_round_trip() {
+        for cell in all_cells() {
+            assert_eq!(GRID.cell_at(GRID.world_position(cell)), Some(cell));
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use bevy::prelude::*;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::config::SimConfig;
+use crate::Grid;
+
+//a PNG decoded to RGBA between 0 and 1, whatever bit depth and color type it was stored in.
+//Row 0 is the top of the image, which is the top (highest y) of the map.
+pub struct Picture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Decode(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "{}", e),
+            ImportError::Decode(e) => write!(f, "not a readable PNG: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for ImportError {
+    fn from(e: png::DecodingError) -> Self {
+        match e {
+            png::DecodingError::IoError(e) => ImportError::Io(e),
+            e => ImportError::Decode(e.to_string()),
+        }
+    }
+}
+
+pub fn read_png(path: &Path) -> Result<Picture, ImportError> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    //palettes become RGB and 1, 2 and 4 bit images become 8 bit, 16 bit is kept for smoother heightmaps
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let channels = info.color_type.samples();
+    let samples: Vec<f32> = match info.bit_depth {
+        png::BitDepth::Sixteen => buffer[..info.buffer_size()].chunks(2).map(|sample| u16::from_be_bytes([sample[0], sample[1]]) as f32 / 65535.0).collect(),
+        _ => buffer[..info.buffer_size()].iter().map(|sample| *sample as f32 / 255.0).collect(),
+    };
+    let pixels = samples
+        .chunks(channels)
+        .map(|pixel| match *pixel {
+            [gray] => [gray, gray, gray, 1.0],
+            [gray, alpha] => [gray, gray, gray, alpha],
+            [r, g, b] => [r, g, b, 1.0],
+            [r, g, b, alpha] => [r, g, b, alpha],
+            _ => unreachable!(),
+        })
+        .collect();
+    Ok(Picture { width: info.width as usize, height: info.height as usize, pixels })
+}
+
+impl Picture {
+    //where the center of a cell lands on the picture, in pixels. The picture is stretched over the whole map,
+    //odd rows are half a cell to the right like everywhere else.
+    fn locate(&self, position: (usize, usize), config: &SimConfig) -> (f32, f32) {
+        let (x, y) = position;
+        let u = (x as f32 + 0.5 + 0.5 * (y % 2) as f32) / config.width as f32;
+        let v = 1.0 - (y as f32 + 0.5) / config.height as f32;
+        (u * self.width as f32, v * self.height as f32)
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> [f32; 4] {
+        //the map wraps around horizontally, so the picture does too
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    //blended from the four nearest pixels, for smooth terrain when the picture is smaller than the map
+    pub fn sample(&self, position: (usize, usize), config: &SimConfig) -> [f32; 4] {
+        let (u, v) = self.locate(position, config);
+        let (u, v) = (u - 0.5, v - 0.5);
+        let (x, y) = (u.floor() as i64, v.floor() as i64);
+        let (fx, fy) = (u - u.floor(), v - v.floor());
+        let mut color = [0.0; 4];
+        for (dx, dy, weight) in [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)] {
+            for (channel, value) in color.iter_mut().zip(self.pixel(x + dx, y + dy)) {
+                *channel += value * weight;
+            }
+        }
+        color
+    }
+
+    //the single pixel under a cell, for colors that mean something and mustn't be blended
+    pub fn nearest(&self, position: (usize, usize), config: &SimConfig) -> [f32; 4] {
+        let (u, v) = self.locate(position, config);
+        self.pixel(u.floor() as i64, v.floor() as i64)
+    }
+
+    //brightness of a grayscale heightmap, used as elevation
+    pub fn elevation(&self, position: (usize, usize), config: &SimConfig) -> f32 {
+        let [r, g, b, _] = self.sample(position, config);
+        (r + g + b) / 3.0
+    }
+
+    //the color of an ownership map under a cell. Black, white and transparent pixels belong to nobody.
+    pub fn owner_color(&self, position: (usize, usize), config: &SimConfig) -> Option<[u8; 3]> {
+        let [r, g, b, alpha] = self.nearest(position, config);
+        let color = [r, g, b].map(|channel| (channel * 255.0).round() as u8);
+        (alpha >= 0.5 && color != [0, 0, 0] && color != [255, 255, 255]).then_some(color)
+    }
+}
+
+//pictures from --heightmap and --owners and terrain from --terrain, used by setup in place of noise and random empires
+#[derive(Resource, Default)]
+pub struct WorldImport {
+    pub heightmap: Option<Picture>,
+    pub owners: Option<Picture>,
+    pub terrain: Option<Grid>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{EmpireId, MapData};
+    use crate::tests::imported_world;
+    use std::collections::{HashMap, HashSet};
+    use std::path::PathBuf;
+
+    fn write(name: &str, color: png::ColorType, depth: png::BitDepth, width: u32, data: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("empires-import-{}-{}.png", name, std::process::id()));
+        let mut encoder = png::Encoder::new(File::create(&path).unwrap(), width, 1);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        encoder.write_header().unwrap().write_image_data(data).unwrap();
+        path
+    }
+
+    fn read(path: PathBuf) -> Vec<[f32; 4]> {
+        let picture = read_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((picture.width, picture.height), (picture.pixels.len(), 1));
+        picture.pixels
+    }
+
+    #[test]
+    fn read_png_turns_any_format_into_rgba() {
+        let gray = write("gray8", png::ColorType::Grayscale, png::BitDepth::Eight, 2, &[0, 255]);
+        assert_eq!(read(gray), [[0.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0]]);
+        let deep = write("gray16", png::ColorType::Grayscale, png::BitDepth::Sixteen, 2, &[0x00, 0x01, 0xff, 0xff]);
+        let pixels = read(deep);
+        assert_eq!(pixels[0][0], 1.0 / 65535.0);
+        assert_eq!(pixels[1], [1.0; 4]);
+        let rgba = write("rgba", png::ColorType::Rgba, png::BitDepth::Eight, 1, &[255, 0, 51, 102]);
+        assert_eq!(read(rgba), [[1.0, 0.0, 0.2, 0.4]]);
+    }
+
+    //a picture the same size as a 4x2 map, every pixel a different gray
+    fn ramp() -> (Picture, SimConfig) {
+        let mut config = SimConfig::default();
+        (config.width, config.height) = (4, 2);
+        let pixels = (0..8).map(|index| [index as f32 / 10.0, index as f32 / 10.0, index as f32 / 10.0, 1.0]).collect();
+        (Picture { width: 4, height: 2, pixels }, config)
+    }
+
+    #[test]
+    fn cells_map_onto_the_picture() {
+        let (picture, config) = ramp();
+        let gray = |color: [f32; 4]| (color[0] * 10.0).round() as usize;
+        for x in 0..4 {
+            //row 0 of the map is the bottom row of the picture
+            assert_eq!(gray(picture.nearest((x, 0), &config)), 4 + x);
+            assert_eq!(gray(picture.sample((x, 0), &config)), 4 + x);
+            //odd rows are half a cell to the right, so the last cell wraps around to the first pixel
+            assert_eq!(gray(picture.nearest((x, 1), &config)), (x + 1) % 4);
+            let blended = picture.sample((x, 1), &config)[0];
+            assert!((blended - (x + (x + 1) % 4) as f32 / 20.0).abs() < 1e-6, "{} at ({}, 1)", blended, x);
+        }
+    }
+
+    #[test]
+    fn black_white_and_transparent_belong_to_nobody() {
+        let mut config = SimConfig::default();
+        (config.width, config.height) = (4, 1);
+        let pixels = vec![[0.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0], [1.0, 0.0, 0.0, 0.2], [1.0, 0.0, 0.0, 1.0]];
+        let picture = Picture { width: 4, height: 1, pixels };
+        let owners: Vec<Option<[u8; 3]>> = (0..4).map(|x| picture.owner_color((x, 0), &config)).collect();
+        assert_eq!(owners, [None, None, None, Some([255, 0, 0])]);
+    }
+
+    #[test]
+    fn every_color_is_one_empire() {
+        let mut config = SimConfig::default();
+        (config.width, config.height) = (32, 16);
+        //land everywhere, owned in four stripes: red, blue, nobody, green
+        let heightmap = Picture { width: 1, height: 1, pixels: vec![[1.0; 4]] };
+        let stripes = [[1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0], [0.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0]];
+        let owners = Picture { width: 4, height: 1, pixels: stripes.to_vec() };
+        let import = WorldImport { heightmap: Some(heightmap), owners: Some(owners), terrain: None };
+        let colors: Vec<Option<[u8; 3]>> = (0..config.width * config.height).map(|index| import.owners.as_ref().unwrap().owner_color((index % 32, index / 32), &config)).collect();
+
+        let app = imported_world(config, 1, import);
+        let map = app.world().resource::<MapData>();
+        assert_eq!(map.empires.len(), 3);
+        let mut found: HashMap<[u8; 3], HashSet<EmpireId>> = HashMap::new();
+        for (color, empire) in colors.into_iter().zip(&map.front.empire) {
+            match color {
+                Some(color) => {
+                    found.entry(color).or_default().insert(empire.expect("an owned color without an empire"));
+                }
+                None => assert_eq!(*empire, None),
+            }
+        }
+        assert_eq!(found.len(), 3);
+        let ids: HashSet<EmpireId> = found.values().flatten().copied().collect();
+        assert!(found.values().all(|ids| ids.len() == 1));
+        assert_eq!(ids.len(), 3);
+    }
+}
@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use std::fmt::Write;
+
+use crate::config::SimConfig;
+use crate::landmass::Landmasses;
+use crate::map::MapData;
+use crate::water::WaterBody;
+use crate::worldgen::{Biome, BIOME, MOISTURE, RIVER, TEMPERATURE, WATER};
+use crate::{Cell, Cells, Grid};
+
+//the cell under the mouse gets a tooltip, clicking a cell pins a panel for it. Right click unpins.
+#[derive(Resource, Default)]
+pub struct Inspector {
+    pub hovered: Option<(usize, usize)>,
+    pub pinned: Option<(usize, usize)>,
+}
+
+#[derive(Component)]
+pub enum InspectorPanel {
+    Tooltip,
+    Pinned,
+}
+
+fn panel_text(font_size: f32) -> TextBundle {
+    TextBundle::from_section("", TextStyle { font_size, color: Color::WHITE, ..Default::default() })
+        .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.7))
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            padding: UiRect::all(Val::Px(4.0)),
+            display: Display::None,
+            ..Default::default()
+        })
+}
+
+pub fn setup_inspector(mut commands: Commands) {
+    commands.insert_resource(Inspector::default());
+    commands.spawn((panel_text(14.0), InspectorPanel::Tooltip));
+    let mut pinned = panel_text(16.0);
+    pinned.style.left = Val::Px(10.0);
+    pinned.style.bottom = Val::Px(10.0);
+    commands.spawn((pinned, InspectorPanel::Pinned));
+}
+
+//everything on the Cell plus its owner and climate, one value per line
+fn describe(cell: &Cell, land: bool, layers: &[f32], cell_map: &MapData, landmasses: &Landmasses) -> String {
+    let (x, y) = cell.position;
+    if !land {
+        let water = WaterBody::from_layer(layers[WATER]).name();
+        return format!("{}{} ({}, {})\nterrain: {:.4}", water[..1].to_uppercase(), &water[1..], x, y, cell.terrain);
+    }
+    let mut text = format!("Cell ({}, {})\n", x, y);
+    match cell.empire {
+        Some(id) => {
+            let empire = cell_map.empire(id);
+            let _ = writeln!(text, "empire: {} (hue {:.0}, aggression {:.3}, tech {:.5})", id, empire.hue, empire.aggression, empire.tech);
+        }
+        None => text.push_str("empire: none\n"),
+    }
+    if let Some(id) = landmasses.of(cell_map.index(cell.position)) {
+        let landmass = landmasses.get(id);
+        let _ = write!(text, "landmass: {}, {} empires", landmass, landmass.ownership(cell_map).0.len());
+        match landmass.first_invasion {
+            Some(invasion) => {
+                let _ = writeln!(text, ", invaded at tick {} from {}", invasion.tick, landmasses.get(invasion.from).name);
+            }
+            None => text.push('\n'),
+        }
+    }
+    let _ = writeln!(text, "strength: {:.4}", cell.strength);
+    let _ = writeln!(text, "need: {:.4}", cell.need);
+    let _ = writeln!(text, "boat_need: {:.4}", cell.boat_need);
+    let _ = writeln!(text, "send_target: ({}, {})", cell.send_target.0, cell.send_target.1);
+    let _ = writeln!(text, "send_amount: {:.4}", cell.send_amount);
+    let _ = writeln!(text, "age: {}", cell.age);
+    let _ = writeln!(text, "terrain: {:.4}", cell.terrain);
+    let _ = writeln!(text, "biome: {} (moisture {:.3}, temperature {:.3})", Biome::from_layer(layers[BIOME]).name(), layers[MOISTURE], layers[TEMPERATURE]);
+    let _ = writeln!(text, "terrain_factor: {:.4}", cell.terrain_factor);
+    let _ = writeln!(text, "need_factor: {:.4}", cell.need_factor);
+    if cell.lake_growth > 0.0 {
+        let _ = writeln!(text, "lake shore (growth +{:.3})", cell.lake_growth);
+    }
+    if cell.river_growth > 0.0 || cell.river_speed > 0.0 {
+        let _ = writeln!(text, "river: {:.1} flow (growth +{:.3}, speed +{:.3})", layers[RIVER], cell.river_growth, cell.river_speed);
+    }
+    let _ = write!(text, "last_boat: {}", cell.last_boat);
+    text
+}
+
+//find the cell under the cursor and handle clicks
+pub fn update_inspector_system(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    interactions: Query<&Interaction>,
+    config: Res<SimConfig>,
+    mut inspector: ResMut<Inspector>,
+) {
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.get_single(), cameras.get_single()) else {
+        return;
+    };
+    //the mouse is over a button in some panel, not the map
+    if interactions.iter().any(|interaction| *interaction != Interaction::None) {
+        inspector.hovered = None;
+        return;
+    }
+    inspector.hovered = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor))
+        .and_then(|world| config.hex_grid().cell_at(world));
+    if mouse.just_pressed(MouseButton::Left) && inspector.hovered.is_some() {
+        inspector.pinned = inspector.hovered;
+    }
+    if mouse.just_pressed(MouseButton::Right) {
+        inspector.pinned = None;
+    }
+}
+
+//refresh both panels every frame so the numbers stay live while the simulation runs
+#[allow(clippy::too_many_arguments)]
+pub fn draw_inspector_system(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    inspector: Res<Inspector>,
+    cells: Res<Cells>,
+    cell_map: Res<MapData>,
+    grid: Res<Grid>,
+    landmasses: Res<Landmasses>,
+    config: Res<SimConfig>,
+    mut panels: Query<(&mut Text, &mut Style, &InspectorPanel)>,
+) {
+    let describe_at = |(x, y): (usize, usize)| {
+        let index = y * config.width + x;
+        cells.0.get(index).map(|cell| describe(cell, cell_map.land[index], &grid.data[x][y], &cell_map, &landmasses))
+    };
+    let cursor = windows.get_single().ok().and_then(|window| window.cursor_position());
+
+    for (mut text, mut style, panel) in panels.iter_mut() {
+        let description = match panel {
+            InspectorPanel::Pinned => inspector.pinned.and_then(describe_at).map(|description| format!("Pinned (right click to clear)\n{}", description)),
+            InspectorPanel::Tooltip => inspector.hovered.and_then(describe_at),
+        };
+        let Some(description) = description else {
+            style.display = Display::None;
+            continue;
+        };
+        text.sections[0].value = description;
+        style.display = Display::Flex;
+        //the tooltip follows the mouse, the pinned panel stays in the corner
+        if let (InspectorPanel::Tooltip, Some(cursor)) = (panel, cursor) {
+            style.left = Val::Px(cursor.x + 16.0);
+            style.top = Val::Px(cursor.y + 16.0);
+        }
+    }
+}
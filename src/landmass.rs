@@ -0,0 +1,272 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::config::SimConfig;
+use crate::events::{BoatLaunched, BoatLanded, LandmassInvaded};
+use crate::map::{EmpireId, MapData, Neighbors, NO_CELL};
+use crate::rng::{rng_for, RngStream, WorldSeed};
+
+//every connected stretch of land is a landmass, continents are the ones with at least config.continent_size cells.
+//They're numbered from the largest down, so Landmass 0 is the biggest continent.
+
+//names are strung together from these, a few syllables and an ending
+const ONSETS: [&str; 18] = ["b", "d", "g", "k", "l", "m", "n", "r", "s", "t", "v", "z", "th", "br", "kr", "st", "dr", "gl"];
+const VOWELS: [&str; 8] = ["a", "e", "i", "o", "u", "ae", "ai", "ou"];
+const ENDINGS: [&str; 8] = ["", "", "n", "r", "s", "th", "ria", "dor"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub struct LandmassId(pub u32);
+
+impl LandmassId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+//a boat from another landmass coming ashore
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Invasion {
+    pub tick: u64,
+    pub empire: EmpireId,
+    pub from: LandmassId,
+}
+
+pub struct Landmass {
+    pub name: String,
+    pub continent: bool,
+    //indices of its cells, y * width + x
+    pub cells: Vec<u32>,
+    pub first_invasion: Option<Invasion>,
+}
+
+impl Landmass {
+    pub fn kind(&self) -> &'static str {
+        if self.continent {
+            "continent"
+        } else {
+            "island"
+        }
+    }
+
+    //cells held by each empire, largest first, and how many belong to nobody
+    pub fn ownership(&self, cell_map: &MapData) -> (Vec<(EmpireId, u32)>, u32) {
+        let mut owners: HashMap<EmpireId, u32> = HashMap::default();
+        let mut unowned = 0;
+        for &index in &self.cells {
+            match cell_map.front.empire[index as usize] {
+                Some(empire) => *owners.entry(empire).or_insert(0) += 1,
+                None => unowned += 1,
+            }
+        }
+        let mut owners: Vec<(EmpireId, u32)> = owners.into_iter().collect();
+        owners.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        (owners, unowned)
+    }
+}
+
+impl fmt::Display for Landmass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}, {} cells)", self.name, self.kind(), self.cells.len())
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Landmasses {
+    //the landmass of every cell, NO_CELL for water
+    of: Vec<u32>,
+    pub list: Vec<Landmass>,
+    //boats at sea and where they set out from
+    launched: HashMap<u64, LandmassId>,
+}
+
+//what a run has found out about its landmasses so far, the rest never changes. Kept in saves, and as a resource
+//between loading one and labelling its land.
+#[derive(Resource, Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Invasions {
+    first: Vec<Option<Invasion>>,
+    launched: HashMap<u64, LandmassId>,
+}
+
+fn name(rng: &mut impl Rng) -> String {
+    let mut name: String = (0..rng.gen_range(2..=3)).map(|_| format!("{}{}", ONSETS.choose(rng).unwrap(), VOWELS.choose(rng).unwrap())).collect();
+    name.push_str(ENDINGS.choose(rng).unwrap());
+    name[..1].to_uppercase() + &name[1..]
+}
+
+impl Landmasses {
+    pub fn label(land: &[bool], neighbors: &Neighbors, config: &SimConfig, seed: WorldSeed) -> Self {
+        let mut of = vec![NO_CELL; land.len()];
+        let mut found: Vec<Vec<u32>> = Vec::new();
+        for start in 0..land.len() {
+            if !land[start] || of[start] != NO_CELL {
+                continue;
+            }
+            let id = found.len() as u32;
+            of[start] = id;
+            let mut cells = vec![start as u32];
+            let mut next = 0;
+            while let Some(&index) = cells.get(next) {
+                next += 1;
+                for &neighbor in &neighbors.0[index as usize] {
+                    if neighbor != NO_CELL && land[neighbor as usize] && of[neighbor as usize] == NO_CELL {
+                        of[neighbor as usize] = id;
+                        cells.push(neighbor);
+                    }
+                }
+            }
+            found.push(cells);
+        }
+
+        //largest first, ties stay in the order they were found
+        found.sort_by_key(|cells| std::cmp::Reverse(cells.len()));
+        let mut names: Vec<String> = Vec::new();
+        let list = found
+            .into_iter()
+            .enumerate()
+            .map(|(id, cells)| {
+                for &index in &cells {
+                    of[index as usize] = id as u32;
+                }
+                let mut rng = rng_for(seed, RngStream::Names, 0, id as u64);
+                let mut landmass_name = name(&mut rng);
+                while names.contains(&landmass_name) {
+                    landmass_name = name(&mut rng);
+                }
+                names.push(landmass_name.clone());
+                Landmass { name: landmass_name, continent: cells.len() >= config.continent_size, cells, first_invasion: None }
+            })
+            .collect();
+        Landmasses { of, list, launched: HashMap::default() }
+    }
+
+    pub fn invasions(&self) -> Invasions {
+        Invasions { first: self.list.iter().map(|landmass| landmass.first_invasion).collect(), launched: self.launched.clone() }
+    }
+
+    //go back to what was known at an earlier tick
+    pub fn restore(&mut self, invasions: Invasions) {
+        for (landmass, first) in self.list.iter_mut().zip(invasions.first) {
+            landmass.first_invasion = first;
+        }
+        self.launched = invasions.launched;
+    }
+
+    //None for water
+    pub fn of(&self, index: usize) -> Option<LandmassId> {
+        self.of.get(index).filter(|id| **id != NO_CELL).map(|id| LandmassId(*id))
+    }
+
+    pub fn get(&self, id: LandmassId) -> &Landmass {
+        &self.list[id.index()]
+    }
+
+    //one line per landmass of at least min_cells, for the end of a headless run
+    pub fn report(&self, cell_map: &MapData, min_cells: usize) -> Vec<String> {
+        self.list
+            .iter()
+            .take_while(|landmass| landmass.cells.len() >= min_cells)
+            .map(|landmass| {
+                let (owners, unowned) = landmass.ownership(cell_map);
+                let share = |cells: u32| cells as f32 / landmass.cells.len() as f32 * 100.0;
+                let mut line = format!("  {}\t{} empires", landmass, owners.len());
+                for (empire, cells) in owners.iter().take(3) {
+                    line += &format!(", Empire {} {:.0}%", empire, share(*cells));
+                }
+                if share(unowned) >= 0.5 {
+                    line += &format!(", unowned {:.0}%", share(unowned));
+                }
+                match landmass.first_invasion {
+                    Some(invasion) => line += &format!("\tfirst invaded at tick {} by Empire {} from {}", invasion.tick, invasion.empire, self.get(invasion.from).name),
+                    None => line += "\tnever invaded",
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+//the world is built (or loaded) during Startup, label its land once it exists. A loaded world picks up the invasions it was saved with.
+pub fn label_landmasses_system(
+    mut commands: Commands,
+    mut landmasses: ResMut<Landmasses>,
+    loaded: Option<Res<Invasions>>,
+    cell_map: Res<MapData>,
+    neighbors: Res<Neighbors>,
+    config: Res<SimConfig>,
+    seed: Res<WorldSeed>,
+) {
+    *landmasses = Landmasses::label(&cell_map.land, &neighbors, &config, *seed);
+    if let Some(loaded) = loaded {
+        landmasses.restore(loaded.clone());
+        commands.remove_resource::<Invasions>();
+    }
+    let continents = landmasses.list.iter().filter(|landmass| landmass.continent).count();
+    println!("{} continents and {} islands", continents, landmasses.list.len() - continents);
+}
+
+//remember where boats set out from, and note the first time each landmass is reached by a boat from another one
+pub fn track_invasions_system(
+    mut landmasses: ResMut<Landmasses>,
+    cell_map: Res<MapData>,
+    mut launched: EventReader<BoatLaunched>,
+    mut landed: EventReader<BoatLanded>,
+    mut invaded: EventWriter<LandmassInvaded>,
+) {
+    let landmasses = &mut *landmasses;
+    for event in landed.read() {
+        let (Some(from), Some(to)) = (landmasses.launched.remove(&event.boat), landmasses.of(cell_map.index(event.position))) else {
+            continue;
+        };
+        let landmass = &mut landmasses.list[to.index()];
+        if from != to && landmass.first_invasion.is_none() {
+            landmass.first_invasion = Some(Invasion { tick: event.tick, empire: event.empire, from });
+            invaded.send(LandmassInvaded { tick: event.tick, landmass: to, from, empire: event.empire, position: event.position });
+        }
+    }
+    for event in launched.read() {
+        if let Some(from) = landmasses.of(cell_map.index(event.from)) {
+            landmasses.launched.insert(event.boat, from);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_numbers_landmasses_from_the_largest() {
+        let mut config = SimConfig::default();
+        (config.width, config.height, config.continent_size) = (10, 4, 5);
+        let width = config.width;
+        let at = |x: usize, y: usize| y * width + x;
+        //a continent two columns wide, an island of three, and two single cells of which (9, 1) is found first
+        let mut land = vec![false; width * config.height];
+        for y in 0..config.height {
+            land[at(1, y)] = true;
+            land[at(2, y)] = true;
+        }
+        for position in [(5, 1), (6, 1), (7, 1), (9, 1), (5, 3)] {
+            land[at(position.0, position.1)] = true;
+        }
+
+        let landmasses = Landmasses::label(&land, &Neighbors::new(&config.hex_grid()), &config, WorldSeed(4));
+        let sizes: Vec<usize> = landmasses.list.iter().map(|landmass| landmass.cells.len()).collect();
+        assert_eq!(sizes, [8, 3, 1, 1]);
+        let kinds: Vec<&str> = landmasses.list.iter().map(|landmass| landmass.kind()).collect();
+        assert_eq!(kinds, ["continent", "island", "island", "island"]);
+        assert_eq!(landmasses.of(at(2, 3)), Some(LandmassId(0)));
+        assert_eq!(landmasses.of(at(7, 1)), Some(LandmassId(1)));
+        assert_eq!(landmasses.of(at(9, 1)), Some(LandmassId(2)));
+        assert_eq!(landmasses.of(at(5, 3)), Some(LandmassId(3)));
+        assert_eq!(landmasses.of(at(0, 0)), None);
+        for (id, landmass) in landmasses.list.iter().enumerate() {
+            assert!(landmass.cells.iter().all(|&index| landmasses.of(index as usize) == Some(LandmassId(id as u32))));
+            assert_eq!(landmasses.list.iter().filter(|other| other.name == landmass.name).count(), 1);
+        }
+    }
+}
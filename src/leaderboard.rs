@@ -0,0 +1,277 @@
+use bevy::prelude::*;
+use std::cmp::Reverse;
+
+use crate::config::SimConfig;
+use crate::map::{EmpireId, MapData};
+use crate::GameData;
+
+const ROWS: usize = 15;
+//recounting every cell each frame is wasted work, the panel only needs to keep up with a reader
+const REFRESH_SECONDS: f32 = 0.5;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Territory,
+    Strength,
+    Tech,
+    Age,
+}
+
+impl SortKey {
+    const ALL: [SortKey; 4] = [SortKey::Territory, SortKey::Strength, SortKey::Tech, SortKey::Age];
+
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Territory => "Cells",
+            SortKey::Strength => "Strength",
+            SortKey::Tech => "Tech",
+            SortKey::Age => "Age",
+        }
+    }
+}
+
+//one live empire, summed over the cells it owns
+#[derive(Clone, Copy)]
+pub struct EmpireStats {
+    pub id: EmpireId,
+    pub cells: usize,
+    pub strength: f32,
+    pub tech: f32,
+    //ticks since it was founded
+    pub age: u64,
+}
+
+#[derive(Resource)]
+pub struct Leaderboard {
+    pub visible: bool,
+    pub sort: SortKey,
+    pub rows: Vec<EmpireStats>,
+    //the empire picked in the panel, the renderer dims everyone else
+    pub selected: Option<EmpireId>,
+    //set when a row is clicked, the camera moves once the empire's territory has been found
+    center_on: Option<EmpireId>,
+    refresh: Timer,
+}
+
+impl Leaderboard {
+    //the rows are recounted on the next update instead of when the timer next runs out
+    fn refresh_now(&mut self) {
+        let duration = self.refresh.duration();
+        self.refresh.set_elapsed(duration);
+    }
+}
+
+#[derive(Component)]
+pub struct LeaderboardPanel;
+
+#[derive(Component)]
+pub struct SortButton(SortKey);
+
+#[derive(Component)]
+pub struct LeaderboardRow(usize);
+
+pub fn setup_leaderboard(mut commands: Commands) {
+    let mut leaderboard = Leaderboard {
+        visible: true,
+        sort: SortKey::Territory,
+        rows: Vec::new(),
+        selected: None,
+        center_on: None,
+        refresh: Timer::from_seconds(REFRESH_SECONDS, TimerMode::Repeating),
+    };
+    leaderboard.refresh_now();
+    commands.insert_resource(leaderboard);
+    let text_style = TextStyle { font_size: 14.0, color: Color::WHITE, ..Default::default() };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(10.0),
+                    top: Val::Px(50.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(4.0)),
+                    row_gap: Val::Px(2.0),
+                    ..Default::default()
+                },
+                background_color: Color::srgba(0.0, 0.0, 0.0, 0.7).into(),
+                ..Default::default()
+            },
+            LeaderboardPanel,
+        ))
+        .with_children(|panel| {
+            panel.spawn(TextBundle::from_section("Empires (L to hide, click a column to sort)", text_style.clone()));
+            panel
+                .spawn(NodeBundle { style: Style { column_gap: Val::Px(4.0), ..Default::default() }, ..Default::default() })
+                .with_children(|header| {
+                    for key in SortKey::ALL {
+                        header
+                            .spawn((ButtonBundle { style: Style { padding: UiRect::horizontal(Val::Px(4.0)), ..Default::default() }, ..Default::default() }, SortButton(key)))
+                            .with_children(|button| {
+                                button.spawn(TextBundle::from_section(key.label(), text_style.clone()));
+                            });
+                    }
+                });
+            for row in 0..ROWS {
+                panel
+                    .spawn((ButtonBundle { style: Style { padding: UiRect::horizontal(Val::Px(4.0)), ..Default::default() }, ..Default::default() }, LeaderboardRow(row)))
+                    .with_children(|button| {
+                        button.spawn(TextBundle::from_section("", text_style.clone()));
+                    });
+            }
+        });
+}
+
+//totals for every empire that still owns a cell, in no particular order
+pub fn empire_stats(cell_map: &MapData, tick: u64) -> Vec<EmpireStats> {
+    let mut stats: Vec<EmpireStats> = cell_map
+        .empires
+        .iter()
+        .enumerate()
+        .map(|(index, empire)| EmpireStats { id: EmpireId(index as u32), cells: 0, strength: 0.0, tech: empire.tech, age: tick.saturating_sub(empire.founded) })
+        .collect();
+    let front = &cell_map.front;
+    for (index, empire) in front.empire.iter().enumerate() {
+        if let Some(empire) = empire {
+            let stats = &mut stats[empire.index()];
+            stats.cells += 1;
+            stats.strength += front.strength[index];
+        }
+    }
+    stats.retain(|stats| stats.cells > 0);
+    stats
+}
+
+//the middle of an empire's territory. x wraps around, so it's averaged as an angle.
+fn territory_center(cell_map: &MapData, id: EmpireId) -> Option<(usize, usize)> {
+    let (mut sin, mut cos, mut y_sum, mut count) = (0.0, 0.0, 0.0, 0usize);
+    let turn = std::f32::consts::TAU / cell_map.width as f32;
+    for (index, empire) in cell_map.front.empire.iter().enumerate() {
+        if *empire == Some(id) {
+            let (x, y) = (index % cell_map.width, index / cell_map.width);
+            sin += (x as f32 * turn).sin();
+            cos += (x as f32 * turn).cos();
+            y_sum += y as f32;
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return None;
+    }
+    let x = (sin.atan2(cos) / turn).rem_euclid(cell_map.width as f32).round() as usize % cell_map.width;
+    Some((x, (y_sum / count as f32).round() as usize))
+}
+
+pub fn update_leaderboard_system(time: Res<Time>, keyboard_input: Res<ButtonInput<KeyCode>>, cell_map: Res<MapData>, game_data: Res<GameData>, mut leaderboard: ResMut<Leaderboard>) {
+    if keyboard_input.just_pressed(KeyCode::KeyL) {
+        leaderboard.visible = !leaderboard.visible;
+    }
+    if !leaderboard.refresh.tick(time.delta()).just_finished() {
+        return;
+    }
+    let mut rows = empire_stats(&cell_map, game_data.tick);
+    match leaderboard.sort {
+        SortKey::Territory => rows.sort_by_key(|stats| Reverse(stats.cells)),
+        SortKey::Strength => rows.sort_by(|a, b| b.strength.total_cmp(&a.strength)),
+        SortKey::Tech => rows.sort_by(|a, b| b.tech.total_cmp(&a.tech)),
+        SortKey::Age => rows.sort_by_key(|stats| Reverse(stats.age)),
+    }
+    rows.truncate(ROWS);
+    leaderboard.rows = rows;
+}
+
+pub fn leaderboard_click_system(
+    sort_buttons: Query<(&Interaction, &SortButton), Changed<Interaction>>,
+    rows: Query<(&Interaction, &LeaderboardRow), Changed<Interaction>>,
+    mut leaderboard: ResMut<Leaderboard>,
+) {
+    for (interaction, button) in sort_buttons.iter() {
+        if *interaction == Interaction::Pressed {
+            leaderboard.sort = button.0;
+            //show the new order right away instead of at the next refresh
+            leaderboard.refresh_now();
+        }
+    }
+    for (interaction, row) in rows.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(stats) = leaderboard.rows.get(row.0).copied() else {
+            continue;
+        };
+        //clicking the selected empire again clears the highlight
+        if leaderboard.selected == Some(stats.id) {
+            leaderboard.selected = None;
+        } else {
+            leaderboard.selected = Some(stats.id);
+            leaderboard.center_on = Some(stats.id);
+        }
+    }
+}
+
+pub fn center_camera_system(cell_map: Res<MapData>, config: Res<SimConfig>, mut leaderboard: ResMut<Leaderboard>, mut cameras: Query<&mut Transform, With<Camera2d>>) {
+    let Some(id) = leaderboard.center_on.take() else {
+        return;
+    };
+    let (Some(center), Ok(mut camera)) = (territory_center(&cell_map, id), cameras.get_single_mut()) else {
+        return;
+    };
+    let position = config.hex_grid().world_position(center);
+    camera.translation.x = position.x;
+    camera.translation.y = position.y;
+}
+
+pub fn draw_leaderboard_system(
+    leaderboard: Res<Leaderboard>,
+    cell_map: Res<MapData>,
+    mut panel: Query<&mut Style, With<LeaderboardPanel>>,
+    mut rows: Query<(&LeaderboardRow, &Children, &mut BackgroundColor, &mut Style), Without<LeaderboardPanel>>,
+    mut sort_buttons: Query<(&SortButton, &mut BackgroundColor), Without<LeaderboardRow>>,
+    mut texts: Query<&mut Text>,
+) {
+    if let Ok(mut style) = panel.get_single_mut() {
+        style.display = if leaderboard.visible { Display::Flex } else { Display::None };
+    }
+    for (button, mut background) in sort_buttons.iter_mut() {
+        *background = if button.0 == leaderboard.sort { Color::srgb(0.3, 0.3, 0.5) } else { Color::srgb(0.15, 0.15, 0.15) }.into();
+    }
+    for (row, children, mut background, mut style) in rows.iter_mut() {
+        let Some(stats) = leaderboard.rows.get(row.0) else {
+            style.display = Display::None;
+            continue;
+        };
+        style.display = Display::Flex;
+        let empire = cell_map.empire(stats.id);
+        let lightness = if leaderboard.selected == Some(stats.id) { 0.45 } else { 0.2 };
+        *background = Color::hsla(empire.hue, empire.saturation, lightness, 1.0).into();
+        if let Some(mut text) = children.first().and_then(|child| texts.get_mut(*child).ok()) {
+            text.sections[0].value = format!(
+                "#{} Empire {}: {} cells, strength {:.0}, tech {:.5}, age {}",
+                row.0 + 1,
+                stats.id,
+                stats.cells,
+                stats.strength,
+                stats.tech,
+                stats.age
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Empire;
+
+    #[test]
+    fn an_empire_is_as_old_as_its_founding() {
+        let mut map = MapData::new(3, 1, vec![true; 3]);
+        map.empires = [0, 40, 10].map(|founded| Empire { hue: 0.0, saturation: 0.5, aggression: 0.5, tech: 0.0, founded }).to_vec();
+        //Empire 0 lost every cell it ever held, what it owns now was just taken
+        map.front.empire = vec![Some(EmpireId(0)), Some(EmpireId(1)), Some(EmpireId(1))];
+        map.front.age = vec![1, 50, 3];
+        let stats = empire_stats(&map, 100);
+        let ages: Vec<(EmpireId, u64)> = stats.iter().map(|stats| (stats.id, stats.age)).collect();
+        assert_eq!(ages, [(EmpireId(0), 100), (EmpireId(1), 60)]);
+    }
+}
@@ -0,0 +1,410 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::hex::HexGrid;
+
+//index into MapData::empires. Cells without an owner use None instead of a sentinel.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub struct EmpireId(pub u32);
+
+impl EmpireId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for EmpireId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+//per-empire attributes, rolled when the empire is founded. Only tech changes afterwards.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Empire {
+    pub hue: f32,
+    pub saturation: f32,
+    pub aggression: f32,
+    pub tech: f32,
+    //the tick it was founded on
+    pub founded: u64,
+}
+
+//a boat that reached this cell during the last boat update. Treated like a neighbor sending strength here.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BoatLanding {
+    pub empire: EmpireId,
+    pub strength: f32,
+}
+
+//the part of a Cell its neighbors are allowed to see, copied out after every push and pull.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct CellSnapshot {
+    pub position: (usize, usize),
+    pub empire: Option<EmpireId>,
+    pub strength: f32,
+    pub need: f32,
+    pub send_target: (usize, usize),
+    pub send_amount: f32,
+    pub send_empire: Option<EmpireId>,
+    pub age: u32,
+    //only the last boat to land in a tick counts
+    pub landing: Option<BoatLanding>,
+    pub boat_need: f32,
+}
+
+impl CellSnapshot {
+    //a landing boat as seen by pull: an enemy (or friend) sending its whole strength into this cell.
+    pub fn from_landing(position: (usize, usize), landing: BoatLanding) -> Self {
+        CellSnapshot {
+            position,
+            empire: Some(landing.empire),
+            strength: landing.strength,
+            send_target: position,
+            send_amount: landing.strength,
+            send_empire: Some(landing.empire),
+            ..Default::default()
+        }
+    }
+}
+
+//CellSnapshot stored as one array per field, indexed by y * width + x.
+//Neighbors only touch the fields they read, and rows can be written in parallel by zipping the columns.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CellColumns {
+    pub empire: Vec<Option<EmpireId>>,
+    pub strength: Vec<f32>,
+    pub need: Vec<f32>,
+    pub send_target: Vec<u32>,
+    pub send_amount: Vec<f32>,
+    pub send_empire: Vec<Option<EmpireId>>,
+    pub age: Vec<u32>,
+    pub landing: Vec<Option<BoatLanding>>,
+    pub boat_need: Vec<f32>,
+}
+
+//mutable view of one row of CellColumns
+pub struct CellRowMut<'a> {
+    empire: &'a mut Option<EmpireId>,
+    strength: &'a mut f32,
+    need: &'a mut f32,
+    send_target: &'a mut u32,
+    send_amount: &'a mut f32,
+    send_empire: &'a mut Option<EmpireId>,
+    age: &'a mut u32,
+    landing: &'a mut Option<BoatLanding>,
+    boat_need: &'a mut f32,
+}
+
+impl CellRowMut<'_> {
+    pub fn set(&mut self, snapshot: &CellSnapshot, width: usize) {
+        *self.empire = snapshot.empire;
+        *self.strength = snapshot.strength;
+        *self.need = snapshot.need;
+        *self.send_target = (snapshot.send_target.1 * width + snapshot.send_target.0) as u32;
+        *self.send_amount = snapshot.send_amount;
+        *self.send_empire = snapshot.send_empire;
+        *self.age = snapshot.age;
+        *self.landing = snapshot.landing;
+        *self.boat_need = snapshot.boat_need;
+    }
+}
+
+impl CellColumns {
+    fn new(len: usize) -> Self {
+        CellColumns {
+            empire: vec![None; len],
+            strength: vec![0.0; len],
+            need: vec![0.0; len],
+            send_target: (0..len as u32).collect(),
+            send_amount: vec![0.0; len],
+            send_empire: vec![None; len],
+            age: vec![0; len],
+            landing: vec![None; len],
+            boat_need: vec![0.0; len],
+        }
+    }
+
+    pub fn get(&self, index: usize, width: usize) -> CellSnapshot {
+        let target = self.send_target[index] as usize;
+        CellSnapshot {
+            position: (index % width, index / width),
+            empire: self.empire[index],
+            strength: self.strength[index],
+            need: self.need[index],
+            send_target: (target % width, target / width),
+            send_amount: self.send_amount[index],
+            send_empire: self.send_empire[index],
+            age: self.age[index],
+            landing: self.landing[index],
+            boat_need: self.boat_need[index],
+        }
+    }
+
+    //copy the land neighbors of a cell into data and the navigable water ones into ocean.
+    //Returns how many of each were written, and how many neighbors are lakes.
+    pub fn gather(&self, land: &[bool], navigable: &[bool], width: usize, neighbors: &[u32; 6], data: &mut [CellSnapshot], ocean: &mut [(usize, usize)]) -> (usize, usize, usize) {
+        let (mut found, mut coast, mut lakes) = (0, 0, 0);
+        for &neighbor in neighbors {
+            if neighbor == NO_CELL {
+                continue;
+            }
+            let neighbor = neighbor as usize;
+            if land[neighbor] {
+                data[found] = self.get(neighbor, width);
+                found += 1;
+            } else if navigable[neighbor] {
+                ocean[coast] = (neighbor % width, neighbor / width);
+                coast += 1;
+            } else {
+                lakes += 1;
+            }
+        }
+        (found, coast, lakes)
+    }
+
+    pub fn set(&mut self, index: usize, snapshot: &CellSnapshot, width: usize) {
+        self.row_mut(index).set(snapshot, width);
+    }
+
+    fn row_mut(&mut self, index: usize) -> CellRowMut<'_> {
+        CellRowMut {
+            empire: &mut self.empire[index],
+            strength: &mut self.strength[index],
+            need: &mut self.need[index],
+            send_target: &mut self.send_target[index],
+            send_amount: &mut self.send_amount[index],
+            send_empire: &mut self.send_empire[index],
+            age: &mut self.age[index],
+            landing: &mut self.landing[index],
+            boat_need: &mut self.boat_need[index],
+        }
+    }
+
+    //every row at once, for writing from a rayon loop
+    pub fn par_rows_mut(&mut self) -> impl IndexedParallelIterator<Item = CellRowMut<'_>> {
+        (
+            self.empire.par_iter_mut(),
+            self.strength.par_iter_mut(),
+            self.need.par_iter_mut(),
+            self.send_target.par_iter_mut(),
+            self.send_amount.par_iter_mut(),
+            self.send_empire.par_iter_mut(),
+            self.age.par_iter_mut(),
+            self.landing.par_iter_mut(),
+            self.boat_need.par_iter_mut(),
+        )
+            .into_par_iter()
+            .map(|(empire, strength, need, send_target, send_amount, send_empire, age, landing, boat_need)| CellRowMut {
+                empire,
+                strength,
+                need,
+                send_target,
+                send_amount,
+                send_empire,
+                age,
+                landing,
+                boat_need,
+            })
+    }
+}
+
+//what every cell can see of the others, plus the empires.
+//Double buffered: push and pull read `front` and write `back`, then the two are swapped.
+#[derive(Resource, Serialize, Deserialize, Clone, Default)]
+pub struct MapData {
+    pub width: usize,
+    pub height: usize,
+    //false for ocean. Ocean rows of the columns are never written.
+    pub land: Vec<bool>,
+    //water boats can sail, everything but land and lakes
+    pub navigable: Vec<bool>,
+    pub front: CellColumns,
+    #[serde(skip)]
+    pub back: CellColumns,
+    pub empires: Vec<Empire>,
+}
+
+impl MapData {
+    //all water is navigable until told otherwise
+    pub fn new(width: usize, height: usize, land: Vec<bool>) -> Self {
+        let len = width * height;
+        MapData {
+            width,
+            height,
+            navigable: land.iter().map(|land| !land).collect(),
+            land,
+            front: CellColumns::new(len),
+            back: CellColumns::new(len),
+            empires: Vec::new(),
+        }
+    }
+
+    pub fn index(&self, position: (usize, usize)) -> usize {
+        position.1 * self.width + position.0
+    }
+
+    //the current snapshot of a land cell, None for ocean
+    pub fn get(&self, position: (usize, usize)) -> Option<CellSnapshot> {
+        let index = self.index(position);
+        self.land[index].then(|| self.front.get(index, self.width))
+    }
+
+    //publish what was just written to back. back keeps the old values and gets fully overwritten next phase.
+    pub fn swap(&mut self) {
+        std::mem::swap(&mut self.front, &mut self.back);
+    }
+
+    //back isn't saved, rebuild it after loading
+    pub fn restore_back(&mut self) {
+        self.back = self.front.clone();
+    }
+
+    pub fn empire(&self, id: EmpireId) -> &Empire {
+        &self.empires[id.index()]
+    }
+
+    pub fn empire_mut(&mut self, id: EmpireId) -> &mut Empire {
+        &mut self.empires[id.index()]
+    }
+}
+
+//marks a missing neighbor (off the top or bottom of the map) in the neighbor table
+pub const NO_CELL: u32 = u32::MAX;
+
+//each cell's six neighbors as indices, in hex::Direction::ALL order. Built once from the map's shape.
+#[derive(Resource)]
+pub struct Neighbors(pub Vec<[u32; 6]>);
+
+impl Neighbors {
+    pub fn new(hex_grid: &HexGrid) -> Self {
+        let width = hex_grid.width;
+        Neighbors(
+            (0..hex_grid.width * hex_grid.height)
+                .into_par_iter()
+                .map(|index| {
+                    let mut row = [NO_CELL; 6];
+                    for (direction, (x, y)) in hex_grid.neighbors((index % width, index / width)) {
+                        row[direction.index()] = (y * width + x) as u32;
+                    }
+                    row
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: HexGrid = HexGrid { width: 8, height: 6 };
+
+    fn snapshot(position: (usize, usize), seed: u32) -> CellSnapshot {
+        let value = seed as f32;
+        CellSnapshot {
+            position,
+            empire: Some(EmpireId(seed)),
+            strength: value + 0.5,
+            need: value + 0.25,
+            send_target: ((position.0 + 1) % GRID.width, position.1),
+            send_amount: value + 0.125,
+            send_empire: Some(EmpireId(seed + 1)),
+            age: seed * 2,
+            landing: Some(BoatLanding { empire: EmpireId(seed + 2), strength: value }),
+            boat_need: value + 0.75,
+        }
+    }
+
+    //CellSnapshot has floats in it, so compare what it prints
+    fn same(a: &CellSnapshot, b: &CellSnapshot) -> bool {
+        format!("{:?}", a) == format!("{:?}", b)
+    }
+
+    #[test]
+    fn set_then_get_round_trips() {
+        let mut columns = CellColumns::new(GRID.width * GRID.height);
+        for index in 0..GRID.width * GRID.height {
+            let position = (index % GRID.width, index / GRID.width);
+            columns.set(index, &snapshot(position, index as u32), GRID.width);
+        }
+        for index in 0..GRID.width * GRID.height {
+            let position = (index % GRID.width, index / GRID.width);
+            assert!(same(&columns.get(index, GRID.width), &snapshot(position, index as u32)));
+        }
+    }
+
+    #[test]
+    fn parallel_rows_write_the_same_as_set() {
+        let len = GRID.width * GRID.height;
+        let mut by_set = CellColumns::new(len);
+        let mut by_rows = CellColumns::new(len);
+        for index in 0..len {
+            by_set.set(index, &snapshot((index % GRID.width, index / GRID.width), index as u32), GRID.width);
+        }
+        by_rows.par_rows_mut().enumerate().for_each(|(index, mut row)| row.set(&snapshot((index % GRID.width, index / GRID.width), index as u32), GRID.width));
+        for index in 0..len {
+            assert!(same(&by_set.get(index, GRID.width), &by_rows.get(index, GRID.width)));
+        }
+    }
+
+    #[test]
+    fn swap_publishes_back_and_restore_copies_front() {
+        let mut map = MapData::new(GRID.width, GRID.height, vec![true; GRID.width * GRID.height]);
+        let written = snapshot((3, 2), 7);
+        let index = map.index((3, 2));
+        map.back.set(index, &written, GRID.width);
+        assert!(map.get((3, 2)).unwrap().empire.is_none());
+        map.swap();
+        assert!(same(&map.get((3, 2)).unwrap(), &written));
+        //the old front is now back, until it's rebuilt
+        assert!(map.back.empire[index].is_none());
+        map.restore_back();
+        assert!(same(&map.back.get(index, GRID.width), &written));
+    }
+
+    #[test]
+    fn neighbor_table_matches_the_grid() {
+        let neighbors = Neighbors::new(&GRID);
+        for index in 0..GRID.width * GRID.height {
+            let mut expected = [NO_CELL; 6];
+            for (direction, (x, y)) in GRID.neighbors((index % GRID.width, index / GRID.width)) {
+                expected[direction.index()] = (y * GRID.width + x) as u32;
+            }
+            assert_eq!(neighbors.0[index], expected);
+        }
+        //the bottom row has nothing to the south
+        assert_eq!(neighbors.0[0].iter().filter(|&&neighbor| neighbor == NO_CELL).count(), 2);
+    }
+
+    #[test]
+    fn gather_sorts_neighbors_into_land_coast_and_lakes() {
+        let len = GRID.width * GRID.height;
+        //stripes by column: land, navigable water, lake
+        let kind = |index: usize| index % GRID.width % 3;
+        let land: Vec<bool> = (0..len).map(|index| kind(index) == 0).collect();
+        let navigable: Vec<bool> = (0..len).map(|index| kind(index) == 1).collect();
+        let mut columns = CellColumns::new(len);
+        for index in 0..len {
+            columns.set(index, &snapshot((index % GRID.width, index / GRID.width), index as u32), GRID.width);
+        }
+        let neighbors = Neighbors::new(&GRID);
+        let mut data = [CellSnapshot::default(); 6];
+        let mut ocean = [(0, 0); 6];
+        for index in 0..len {
+            let (found, coast, lakes) = columns.gather(&land, &navigable, GRID.width, &neighbors.0[index], &mut data, &mut ocean);
+            let present: Vec<usize> = neighbors.0[index].iter().filter(|&&neighbor| neighbor != NO_CELL).map(|&neighbor| neighbor as usize).collect();
+            let expected_land: Vec<usize> = present.iter().copied().filter(|&neighbor| land[neighbor]).collect();
+            let expected_coast: Vec<(usize, usize)> = present.iter().filter(|&&neighbor| navigable[neighbor]).map(|&neighbor| (neighbor % GRID.width, neighbor / GRID.width)).collect();
+            assert_eq!(found, expected_land.len());
+            assert_eq!(&ocean[..coast], &expected_coast[..]);
+            assert_eq!(lakes, present.len() - found - coast);
+            //land neighbors come out in direction order, exactly as get returns them
+            for (gathered, &neighbor) in data[..found].iter().zip(&expected_land) {
+                assert!(same(gathered, &columns.get(neighbor, GRID.width)));
+            }
+        }
+    }
+}
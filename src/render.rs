@@ -0,0 +1,317 @@
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+use rayon::prelude::*;
+
+use bevy::color::Mix;
+
+use crate::config::SimConfig;
+use crate::hex::Direction;
+use crate::leaderboard::Leaderboard;
+use crate::map::{CellSnapshot, Empire, EmpireId, MapData};
+use crate::rivers::river_size;
+use crate::water::{is_land, WaterBody};
+use crate::worldgen::{ELEVATION, RIVER, WATER};
+use crate::{GameData, Grid, RenderMode};
+
+//the map is drawn into images of at most CHUNK_SIZE x CHUNK_SIZE cells, so big maps stay under the GPU's texture size limit
+//and a change only re-uploads the chunk it's in.
+const CHUNK_SIZE: usize = 256;
+
+//every cell is two pixels wide so odd rows can be shifted right by half a cell, like the hex layout.
+//The right half of the last cell in an odd row wraps around to the first pixel of that row.
+struct MapChunk {
+    image: Handle<Image>,
+    //first pixel column and first row (lowest y) covered by this chunk
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+#[derive(Resource)]
+pub struct MapImages {
+    chunks: Vec<MapChunk>,
+    chunks_x: usize,
+    //the last color drawn for every cell, a cell is only redrawn when its color changes
+    drawn: Vec<[u8; 4]>,
+    //the mode and highlight on screen, None until the first frame
+    view: Option<(RenderMode, Option<EmpireId>)>,
+    //the owner and the strength and age shades each cell was last colored with
+    seen: Vec<(Option<EmpireId>, u16, u16)>,
+}
+
+impl MapImages {
+    //the chunk and byte offset of a pixel, pixels are counted from the bottom left of the map
+    fn locate(&self, x: usize, y: usize) -> (usize, usize) {
+        let index = (y / CHUNK_SIZE) * self.chunks_x + x / (2 * CHUNK_SIZE);
+        let chunk = &self.chunks[index];
+        //images are stored top row first, but y goes up on screen
+        let row = chunk.y + chunk.height - 1 - y;
+        (index, (row * chunk.width + x - chunk.x) * 4)
+    }
+}
+
+//spawn one sprite per chunk, lined up so cell (x, y) is centered on hex_grid.world_position((x, y)).
+pub fn spawn_map_images(commands: &mut Commands, images: &mut Assets<Image>, config: &SimConfig) {
+    let (width, height) = (config.width, config.height);
+    let chunks_x = width.div_ceil(CHUNK_SIZE);
+    let mut chunks = Vec::new();
+    for y in (0..height).step_by(CHUNK_SIZE) {
+        for x in (0..width).step_by(CHUNK_SIZE) {
+            let (chunk_width, chunk_height) = (2 * CHUNK_SIZE.min(width - x), CHUNK_SIZE.min(height - y));
+            let mut image = Image::new_fill(
+                Extent3d { width: chunk_width as u32, height: chunk_height as u32, depth_or_array_layers: 1 },
+                TextureDimension::D2,
+                &[0, 0, 0, 255],
+                TextureFormat::Rgba8UnormSrgb,
+                RenderAssetUsages::default(),
+            );
+            //keep cells sharp when zoomed in
+            image.sampler = ImageSampler::nearest();
+            let image = images.add(image);
+            //pixel centers are half a pixel in, and cell (0, 0) is centered on the origin
+            let left = x as f32 - 0.5;
+            let bottom = y as f32 - 0.5;
+            commands.spawn(SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(chunk_width as f32 / 2.0, chunk_height as f32)),
+                    anchor: bevy::sprite::Anchor::BottomLeft,
+                    ..Default::default()
+                },
+                texture: image.clone(),
+                transform: Transform::from_xyz(left, bottom, 0.0),
+                ..Default::default()
+            });
+            chunks.push(MapChunk { image, x: 2 * x, y, width: chunk_width, height: chunk_height });
+        }
+    }
+    commands.insert_resource(MapImages { chunks, chunks_x, drawn: vec![[0; 4]; width * height], view: None, seen: vec![(None, 0, 0); width * height] });
+}
+
+//land and ocean with nobody on it, shaded by elevation. Also the whole map in TerrainView.
+pub fn terrain_color(terrain: f32, config: &SimConfig) -> Color {
+    let ocean_cutoff = config.ocean_cutoff;
+    if !is_land(terrain, config) {
+        //ocean
+        let brightness = terrain / 1.5;//cell[0] + 0.01 / (cell[0].sqrt());
+        Color::hsla(240.0, 1.0, brightness, 1.0)
+    } else {
+        //land
+        let brightness = terrain / 1.6;
+        Color::hsla(110.0 + (terrain) * 30.0 * (1.0 / ocean_cutoff), 1.0 - (terrain-ocean_cutoff) * 2.5, brightness, 1.0)
+    }
+}
+
+//lakes are fresh water, lighter and greener than the sea
+fn lake_color(terrain: f32) -> Color {
+    Color::hsla(190.0, 0.8, terrain / 1.5 + 0.1, 1.0)
+}
+
+//the bigger the river, the more it covers the land under it
+fn with_river(color: Color, river: f32) -> Color {
+    if river <= 0.0 {
+        return color;
+    }
+    let water = Srgba::from(Color::hsla(215.0, 0.9, 0.45, 1.0));
+    Srgba::from(color).mix(&water, river * 0.8).into()
+}
+
+//brightness in StrengthView and AgeView, each scaled by the largest on the map
+fn strength_brightness(strength: f32, game_data: &GameData) -> f32 {
+    (strength.ln() / game_data.max_strength.ln()).max(0.0)
+}
+
+fn age_brightness(age: u32, game_data: &GameData) -> f32 {
+    ((age as f32 / game_data.max_age as f32) * 0.5).min(0.5)
+}
+
+//a brightness in steps of 1/1024, what the dirty check compares. Lightness moves a color channel at most twice as
+//fast as itself, so a cell that isn't repainted within a step is off by at most one in any byte.
+fn shade(brightness: f32) -> u16 {
+    (brightness.clamp(0.0, 1.0) * 1024.0) as u16
+}
+
+//an owned cell, in the owner's hue with the render mode picking what the brightness shows
+pub fn empire_color(render_mode: RenderMode, cell: &CellSnapshot, empire: &Empire, terrain: f32, game_data: &GameData, config: &SimConfig) -> Color {
+    let max_strength: f32 = game_data.max_strength;
+    let (e_hue, e_sat, e_tech) = (empire.hue, empire.saturation, empire.tech);
+    match render_mode {
+        RenderMode::StrengthView => {
+            let brightness = strength_brightness(cell.strength, game_data);
+            Color::hsla(e_hue, e_sat, brightness, 1.0)
+        }
+        RenderMode::EmpireView => {
+            Color::hsla(e_hue, e_sat, terrain * 0.8, 1.0)
+        }
+        RenderMode::NeedView => {
+            let mut brightness = cell.need.sqrt() / 32.0;
+            if brightness < 0.0 {
+                brightness = 100.0;
+            }
+            Color::hsla(e_hue, e_sat, brightness, 1.0)
+        }
+        RenderMode::SendView => {
+            //color the cell by the direction it's sending strength in, each of the 6 directions lines up with a primary or secondary color.
+            let angle = config.hex_grid().direction_between(cell.position, cell.send_target).map_or(0.0, Direction::angle);
+            let brightness = (cell.send_amount / max_strength.sqrt()) + 0.1;
+            Color::hsla(angle, 1.0, brightness, 1.0)
+        }
+        RenderMode::AgeView => {
+            let brightness = age_brightness(cell.age, game_data);
+            Color::hsla(e_hue, e_sat, brightness, 1.0)
+        }
+        RenderMode::BoatNeedView => {
+            let brightness = cell.boat_need / 48.0;
+            Color::hsla(e_hue, e_sat, brightness, 1.0)
+        }
+        RenderMode::TechView => {
+            Color::hsla(e_hue, e_sat / 10.0, e_tech / config.max_tech, 1.0)
+        }
+        RenderMode::TerrainView => terrain_color(terrain, config),
+    }
+}
+
+//the color of one cell in a render mode. Ocean cells come in as a default snapshot with no empire.
+//layers is the cell's slot in the Grid, rivers are drawn over the views that show the land.
+pub fn cell_color(render_mode: RenderMode, cell: &CellSnapshot, layers: &[f32], cell_map: &MapData, game_data: &GameData, config: &SimConfig) -> Color {
+    let terrain = layers[ELEVATION];
+    let owner = if render_mode == RenderMode::TerrainView { None } else { cell.empire };
+    let color = match (owner, WaterBody::from_layer(layers[WATER])) {
+        (None, WaterBody::Lake) => lake_color(terrain),
+        (None, _) => terrain_color(terrain, config),
+        (Some(empire), _) => empire_color(render_mode, cell, cell_map.empire(empire), terrain, game_data, config),
+    };
+    match render_mode {
+        RenderMode::TerrainView | RenderMode::EmpireView => with_river(color, river_size(layers[RIVER], config)),
+        _ => color,
+    }
+}
+
+//one cell's color as sRGB bytes
+fn pixel(index: usize, render_mode: RenderMode, grid: &Grid, cell_map: &MapData, game_data: &GameData, config: &SimConfig, highlight: Option<EmpireId>) -> [u8; 4] {
+    let (x, y) = (index % config.width, index / config.width);
+    //some grid spots don't have cells because they are ocean
+    let cell = cell_map.get((x, y)).unwrap_or_default();
+    let mut color = cell_color(render_mode, &cell, &grid.data[x][y], cell_map, game_data, config);
+    //an empire picked in the leaderboard stands out by darkening everything else
+    if highlight.is_some() && cell.empire != highlight {
+        let mut hsla = Hsla::from(color);
+        hsla.lightness *= 0.35;
+        color = hsla.into();
+    }
+    color.to_srgba().to_u8_array()
+}
+
+//every cell's color as sRGB bytes, indexed by y * width + x. Shared by the window and the image exporter.
+pub fn map_colors(render_mode: RenderMode, grid: &Grid, cell_map: &MapData, game_data: &GameData, config: &SimConfig, highlight: Option<EmpireId>) -> Vec<[u8; 4]> {
+    (0..config.width * config.height).into_par_iter().map(|index| pixel(index, render_mode, grid, cell_map, game_data, config, highlight)).collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_colors(
+    grid: Res<Grid>,
+    cell_map: Res<MapData>,
+    render_mode: Res<RenderMode>,
+    game_data: Res<GameData>,
+    config: Res<SimConfig>,
+    leaderboard: Res<Leaderboard>,
+    mut map_images: ResMut<MapImages>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let width = config.width;
+    //let start = Instant::now();
+
+    //nothing to do on frames without a tick (or a rewind, or a replay step), unless the view itself changed
+    let view = (*render_mode, leaderboard.selected);
+    let ticked = cell_map.is_changed() || game_data.is_changed();
+    if !ticked && !grid.is_changed() && map_images.view == Some(view) {
+        return;
+    }
+    //every cell is recolored when the view changes, or when a mode's colors depend on something that isn't tracked per cell
+    let full = map_images.view != Some(view)
+        || grid.is_changed()
+        || match *render_mode {
+            RenderMode::TerrainView | RenderMode::EmpireView | RenderMode::StrengthView | RenderMode::AgeView => false,
+            RenderMode::NeedView | RenderMode::SendView | RenderMode::BoatNeedView | RenderMode::TechView => true,
+        };
+    map_images.view = Some(view);
+
+    //otherwise only the cells whose owner or shade moved since they were drawn. Shades are taken at the current scale,
+    //so a new largest strength or age shows up on exactly the cells it changes.
+    let front = &cell_map.front;
+    let seen = |index: usize| (front.empire[index], shade(strength_brightness(front.strength[index], &game_data)), shade(age_brightness(front.age[index], &game_data)));
+    let dirty: Vec<usize> = (0..width * config.height).into_par_iter().filter(|&index| full || map_images.seen[index] != seen(index)).collect();
+    for &index in &dirty {
+        map_images.seen[index] = seen(index);
+    }
+
+    //work out their colors on many threads, but only keep the ones that differ from what's on screen
+    let changed: Vec<(usize, [u8; 4])> = dirty
+        .into_par_iter()
+        .map(|index| (index, pixel(index, *render_mode, &grid, &cell_map, &game_data, &config, leaderboard.selected)))
+        .filter(|(index, color)| *color != map_images.drawn[*index])
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+
+    //sort the pixel writes by chunk so each image is only fetched (and re-uploaded) once
+    let mut writes: Vec<Vec<(usize, [u8; 4])>> = vec![Vec::new(); map_images.chunks.len()];
+    for (index, color) in changed {
+        map_images.drawn[index] = color;
+        let (x, y) = (index % width, index / width);
+        let left = 2 * x + y % 2;
+        for pixel in [left, (left + 1) % (2 * width)] {
+            let (chunk, offset) = map_images.locate(pixel, y);
+            writes[chunk].push((offset, color));
+        }
+    }
+    for (chunk, writes) in map_images.chunks.iter().zip(writes) {
+        if writes.is_empty() {
+            continue;
+        }
+        let Some(image) = images.get_mut(&chunk.image) else {
+            continue;
+        };
+        for (offset, color) in writes {
+            image.data[offset..offset + 4].copy_from_slice(&color);
+        }
+    }
+    //println!("Render took {:?}", start.elapsed());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //whatever the dirty check treats as unchanged is drawn within a byte of what's on screen
+    #[test]
+    fn a_shade_covers_at_most_one_byte_of_color() {
+        let config = SimConfig::default();
+        let game_data = GameData { max_strength: 500.0, max_age: 300, tick: 0 };
+        let empire = Empire { hue: 200.0, saturation: 0.8, aggression: 0.0, tech: 0.0, founded: 0 };
+        let color = |mode: RenderMode, cell: CellSnapshot| empire_color(mode, &cell, &empire, 0.6, &game_data, &config).to_srgba().to_u8_array();
+        let check = |mode: RenderMode, cells: Vec<CellSnapshot>, key: &dyn Fn(&CellSnapshot) -> u16| {
+            let mut drawn: Option<(u16, [u8; 4])> = None;
+            for cell in cells {
+                let (key, color) = (key(&cell), color(mode, cell));
+                match drawn {
+                    Some((seen, on_screen)) if seen == key => {
+                        assert!(on_screen.iter().zip(color).all(|(a, b)| a.abs_diff(b) <= 1), "{:?} drawn as {:?}, not repainted for {:?}", mode, on_screen, color);
+                    }
+                    _ => drawn = Some((key, color)),
+                }
+            }
+        };
+        let strengths = (0..40000).map(|step| CellSnapshot { strength: 1.0 + step as f32 * 0.0125, ..default() }).collect();
+        check(RenderMode::StrengthView, strengths, &|cell| shade(strength_brightness(cell.strength, &game_data)));
+        let ages = (0..=300).map(|age| CellSnapshot { age, ..default() }).collect();
+        check(RenderMode::AgeView, ages, &|cell| shade(age_brightness(cell.age, &game_data)));
+
+        //a change of one percent is well below a replay's strength step and still repaints
+        let key = |strength: f32| shade(strength_brightness(strength, &game_data));
+        assert_ne!(key(100.0), key(101.0));
+    }
+}
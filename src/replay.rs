@@ -0,0 +1,818 @@
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+use bincode::Options;
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+use crate::config::SimConfig;
+use crate::map::{Empire, EmpireId, MapData, Neighbors, NO_CELL};
+use crate::rng::WorldSeed;
+use crate::sim::SimControl;
+use crate::{Boat, GameData, Grid, RenderMode};
+
+//bump whenever ReplayHeader or Frame changes shape.
+pub const REPLAY_VERSION: u32 = 2;
+const MAGIC: &[u8; 8] = b"EMPREPL\0";
+//a full copy of the map every this many ticks, so seeking never has to apply more deltas than this
+const KEYFRAME_EVERY: u64 = 100;
+//only owners, strength, age and tech are recorded. Need, sends and boat need change on every cell every tick and would
+//make replays many times bigger, so the views that show them aren't available when watching one.
+pub const NOT_RECORDED: [RenderMode; 3] = [RenderMode::NeedView, RenderMode::SendView, RenderMode::BoatNeedView];
+
+//what's needed to draw the world without running it again: terrain for the colors and the shape of the map.
+#[derive(Serialize, Deserialize)]
+struct ReplayHeader {
+    config: SimConfig,
+    seed: u64,
+    grid: Grid,
+    land: Vec<bool>,
+}
+
+//strength is drawn on a log scale, so it's stored as ln(strength) in steps of 1/STRENGTH_STEPS (about 4%).
+//Anything up to 1 is 0, it's drawn black either way.
+const STRENGTH_STEPS: f32 = 24.0;
+
+fn pack(strength: f32) -> u8 {
+    match strength > 1.0 {
+        true => (strength.ln() * STRENGTH_STEPS).round().min(255.0) as u8,
+        false => 0,
+    }
+}
+
+fn unpack(strength: u8) -> f32 {
+    match strength {
+        0 => 0.0,
+        _ => (strength as f32 / STRENGTH_STEPS).exp(),
+    }
+}
+
+//who a cell went to. Nearly every capture comes from next door, so it's stored as the neighbor (in hex::Direction::ALL order)
+//whose owner it was in the last frame. Boats bring the rest.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+enum Owner {
+    Abandoned,
+    Neighbor(u8),
+    Empire(EmpireId),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+enum Change {
+    Keyframe {
+        empire: Vec<Option<EmpireId>>,
+        strength: Vec<u8>,
+        age: Vec<u32>,
+        empires: Vec<Empire>,
+    },
+    //only what can't be predicted from the last frame. Owned cells age by one, captured ones start over and tech decays
+    //every tick, so ages and tech are only written when they did something else.
+    Delta {
+        //(cells since the last change, new owner), counting from the previous change keeps the numbers small
+        owners: Vec<(u32, Owner)>,
+        //almost every cell's strength moves a little every tick, so this is the change of every land cell in index order.
+        //Most are 0 or 1 step, which compresses to next to nothing.
+        strength: Vec<u8>,
+        ages: Vec<(u32, u32)>,
+        tech: Vec<(EmpireId, f32)>,
+    },
+}
+
+//the map at the end of one tick
+#[derive(Serialize, Deserialize, Clone)]
+struct Frame {
+    tick: u64,
+    //(owner, cell index) of every boat at sea
+    boats: Vec<(EmpireId, u32)>,
+    change: Change,
+}
+
+impl Frame {
+    //a corrupt file can decode into frames that don't fit the map, which would index out of bounds when applied.
+    //empires is how many there were after the frame before.
+    fn fits(&self, land: &[bool], neighbors: &Neighbors, empires: usize) -> bool {
+        let cells = land.len();
+        let empires = match &self.change {
+            Change::Keyframe { empires, .. } => empires.len(),
+            Change::Delta { .. } => empires,
+        };
+        let boats = self.boats.iter().all(|(empire, index)| empire.index() < empires && (*index as usize) < cells);
+        match &self.change {
+            Change::Keyframe { empire, strength, age, .. } => {
+                boats && empire.len() == cells && strength.len() == cells && age.len() == cells && empire.iter().flatten().all(|empire| empire.index() < empires)
+            }
+            Change::Delta { owners, strength, ages, tech } => {
+                let mut index = 0;
+                let owners = owners.iter().all(|&(skip, owner)| {
+                    index += skip as usize;
+                    index < cells
+                        && match owner {
+                            Owner::Abandoned => true,
+                            Owner::Neighbor(direction) => neighbors.0[index].get(direction as usize).is_some_and(|&neighbor| neighbor != NO_CELL),
+                            Owner::Empire(empire) => empire.index() < empires,
+                        }
+                });
+                let land = land.iter().filter(|land| **land).count();
+                boats && owners && strength.len() == land && ages.iter().all(|(index, _)| (*index as usize) < cells) && tech.iter().all(|(empire, _)| empire.index() < empires)
+            }
+        }
+    }
+}
+
+//the part of a tick every frame gets for free
+fn advance(map: &mut MapData, tech_decay: f32) {
+    for (age, empire) in map.front.age.iter_mut().zip(&map.front.empire) {
+        if empire.is_some() {
+            *age += 1;
+        }
+    }
+    for empire in &mut map.empires {
+        empire.tech = (empire.tech - tech_decay).max(0.0);
+    }
+}
+
+impl Frame {
+    //packed holds the strengths as stored, so deltas can be added without unpacking the map again
+    fn apply(&self, map: &mut MapData, packed: &mut Vec<u8>, neighbors: &Neighbors, tech_decay: f32) {
+        match &self.change {
+            Change::Keyframe { empire, strength, age, empires } => {
+                map.front.empire.clone_from(empire);
+                packed.clone_from(strength);
+                map.front.strength = strength.iter().map(|strength| unpack(*strength)).collect();
+                map.front.age.clone_from(age);
+                map.empires.clone_from(empires);
+            }
+            Change::Delta { owners, strength, ages, tech } => {
+                //neighbors are looked up before any cell changes hands
+                let mut index = 0;
+                let owners: Vec<(usize, Option<EmpireId>)> = owners
+                    .iter()
+                    .map(|&(skip, owner)| {
+                        index += skip as usize;
+                        let owner = match owner {
+                            Owner::Abandoned => None,
+                            Owner::Neighbor(direction) => map.front.empire[neighbors.0[index][direction as usize] as usize],
+                            Owner::Empire(empire) => Some(empire),
+                        };
+                        (index, owner)
+                    })
+                    .collect();
+                for (index, owner) in owners {
+                    map.front.empire[index] = owner;
+                    if owner.is_some() {
+                        map.front.age[index] = 0;
+                    }
+                }
+                let land = map.land.iter().enumerate().filter(|(_, land)| **land);
+                for ((index, _), change) in land.zip(strength) {
+                    if *change != 0 {
+                        packed[index] = packed[index].wrapping_add(*change);
+                        map.front.strength[index] = unpack(packed[index]);
+                    }
+                }
+                advance(map, tech_decay);
+                for &(index, age) in ages {
+                    map.front.age[index as usize] = age;
+                }
+                for &(empire, tech) in tech {
+                    map.empire_mut(empire).tech = tech;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    NotAReplay,
+    Version(u32),
+    Decode(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "{}", e),
+            ReplayError::NotAReplay => write!(f, "not an empires replay file"),
+            ReplayError::Version(v) => write!(f, "replay file version {} is not supported (expected {})", v, REPLAY_VERSION),
+            ReplayError::Decode(e) => write!(f, "corrupt replay file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+//writes a frame after every tick. File layout: magic, version as little endian u32, then one gzip stream holding
+//the bincode header followed by the frames. The stream is flushed at every keyframe, so a run that dies
+//part way still leaves a replay up to the last keyframe.
+//After a rewind the next frame is a keyframe with an earlier tick than the one before it. It replaces every frame
+//from that tick on, so the replay follows the history that was kept.
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    out: Option<GzEncoder<BufWriter<File>>>,
+    //the map as a player will see it after reading every frame so far, deltas are taken against this
+    shown: MapData,
+    packed: Vec<u8>,
+    tech_decay: f32,
+    //the world was rewound, shown no longer matches it
+    rewound: bool,
+}
+
+impl ReplayRecorder {
+    pub fn create(path: &Path, config: &SimConfig) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&REPLAY_VERSION.to_le_bytes())?;
+        Ok(ReplayRecorder { out: Some(GzEncoder::new(file, Compression::fast())), shown: MapData::default(), packed: Vec::new(), tech_decay: config.tech_decay, rewound: false })
+    }
+
+    //the world went back in time, start over from a keyframe on the next tick
+    pub fn rewind(&mut self) {
+        self.rewound = true;
+    }
+
+    fn write<T: Serialize>(&mut self, value: &T, flush: bool) {
+        let Some(out) = &mut self.out else {
+            return;
+        };
+        //bincode writes field by field, handing the encoder one buffer is a lot faster
+        let result = bincode::DefaultOptions::new().serialize(value).map_err(std::io::Error::other).and_then(|bytes| {
+            out.write_all(&bytes)?;
+            if flush {
+                out.flush()?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            eprintln!("Could not write to the replay, recording stopped: {}", e);
+            self.out = None;
+        }
+    }
+
+    fn keyframe(&self, map: &MapData) -> Change {
+        Change::Keyframe {
+            empire: map.front.empire.clone(),
+            strength: map.front.strength.iter().map(|strength| pack(*strength)).collect(),
+            age: map.front.age.clone(),
+            empires: map.empires.clone(),
+        }
+    }
+
+    //whatever advance() would get wrong is written out, the rest is left to the player
+    fn delta(&self, map: &MapData, neighbors: &Neighbors) -> Change {
+        let (mut owners, mut strength, mut ages) = (Vec::new(), Vec::new(), Vec::new());
+        let (actual, shown) = (&map.front, &self.shown.front);
+        let mut last = 0;
+        for index in (0..map.land.len()).filter(|index| map.land[*index]) {
+            let captured = actual.empire[index] != shown.empire[index];
+            if captured {
+                let neighbor = neighbors.0[index].iter().position(|&neighbor| neighbor != NO_CELL && shown.empire[neighbor as usize] == actual.empire[index]);
+                let owner = match (actual.empire[index], neighbor) {
+                    (None, _) => Owner::Abandoned,
+                    (Some(_), Some(direction)) => Owner::Neighbor(direction as u8),
+                    (Some(empire), None) => Owner::Empire(empire),
+                };
+                owners.push(((index - last) as u32, owner));
+                last = index;
+            }
+            strength.push(pack(actual.strength[index]).wrapping_sub(self.packed[index]));
+            let predicted = match (captured, actual.empire[index]) {
+                (true, Some(_)) => 1,
+                (_, owner) => shown.age[index] + owner.is_some() as u32,
+            };
+            if actual.age[index] != predicted {
+                ages.push((index as u32, actual.age[index]));
+            }
+        }
+        let tech = map
+            .empires
+            .iter()
+            .zip(&self.shown.empires)
+            .enumerate()
+            .filter(|(_, (empire, shown))| empire.tech.to_bits() != (shown.tech - self.tech_decay).max(0.0).to_bits())
+            .map(|(index, (empire, _))| (EmpireId(index as u32), empire.tech))
+            .collect();
+        Change::Delta { owners, strength, ages, tech }
+    }
+
+    fn record(&mut self, tick: u64, map: &MapData, neighbors: &Neighbors, boats: Vec<(EmpireId, u32)>) {
+        let change = match self.rewound || tick.is_multiple_of(KEYFRAME_EVERY) {
+            true => self.keyframe(map),
+            false => self.delta(map, neighbors),
+        };
+        self.rewound = false;
+        let frame = Frame { tick, boats, change };
+        frame.apply(&mut self.shown, &mut self.packed, neighbors, self.tech_decay);
+        self.write(&frame, matches!(frame.change, Change::Keyframe { .. }));
+    }
+}
+
+fn boat_cells(config: &SimConfig, boats: &Query<(&Boat, &Transform)>) -> Vec<(EmpireId, u32)> {
+    let hex_grid = config.hex_grid();
+    let mut cells: Vec<(EmpireId, u32)> = boats
+        .iter()
+        .filter_map(|(boat, transform)| hex_grid.cell_at(transform.translation.truncate()).map(|(x, y)| (boat.empire, (y * config.width + x) as u32)))
+        .collect();
+    //query order isn't stable, sorting keeps replays of the same run identical
+    cells.sort();
+    cells
+}
+
+//the world is built (or loaded) during Startup, so the header and first keyframe are written after it
+#[allow(clippy::too_many_arguments)]
+pub fn start_recording_system(
+    mut recorder: ResMut<ReplayRecorder>,
+    config: Res<SimConfig>,
+    seed: Res<WorldSeed>,
+    grid: Res<Grid>,
+    map: Res<MapData>,
+    game_data: Res<GameData>,
+    neighbors: Res<Neighbors>,
+    boats: Query<(&Boat, &Transform)>,
+) {
+    let header = ReplayHeader { config: config.clone(), seed: seed.0, grid: grid.clone(), land: map.land.clone() };
+    recorder.write(&header, false);
+    recorder.shown = MapData::new(map.width, map.height, map.land.clone());
+    let frame = Frame { tick: game_data.tick, boats: boat_cells(&config, &boats), change: recorder.keyframe(&map) };
+    let recorder = &mut *recorder;
+    frame.apply(&mut recorder.shown, &mut recorder.packed, &neighbors, config.tech_decay);
+    recorder.write(&frame, true);
+}
+
+//runs at the end of every tick when --record is given
+pub fn record_frame_system(
+    mut recorder: ResMut<ReplayRecorder>,
+    config: Res<SimConfig>,
+    map: Res<MapData>,
+    game_data: Res<GameData>,
+    neighbors: Res<Neighbors>,
+    boats: Query<(&Boat, &Transform)>,
+) {
+    recorder.record(game_data.tick, &map, &neighbors, boat_cells(&config, &boats));
+}
+
+//a replay read from --replay, frames are kept decoded in memory so any tick can be reached quickly
+#[derive(Resource)]
+pub struct Replay {
+    pub config: SimConfig,
+    pub seed: u64,
+    //terrain and land, handed over to the world when it's spawned
+    world: Option<(Grid, Vec<bool>)>,
+    frames: Vec<Frame>,
+    //the frame currently on screen
+    position: usize,
+    packed: Vec<u8>,
+}
+
+impl Replay {
+    fn tick(&self) -> u64 {
+        self.frames[self.position].tick
+    }
+
+    fn last_tick(&self) -> u64 {
+        self.frames.last().unwrap().tick
+    }
+
+    fn at_end(&self) -> bool {
+        self.position + 1 == self.frames.len()
+    }
+
+    //show the next frame, deltas only make sense applied in order
+    fn step(&mut self, map: &mut MapData, neighbors: &Neighbors) {
+        if self.at_end() {
+            return;
+        }
+        self.position += 1;
+        self.frames[self.position].apply(map, &mut self.packed, neighbors, self.config.tech_decay);
+    }
+
+    //jump to any frame by starting from the last keyframe before it
+    fn seek(&mut self, position: usize, map: &mut MapData, neighbors: &Neighbors) {
+        let position = position.min(self.frames.len() - 1);
+        let start = self.frames[..=position].iter().rposition(|frame| matches!(frame.change, Change::Keyframe { .. })).unwrap();
+        for frame in &self.frames[start..=position] {
+            frame.apply(map, &mut self.packed, neighbors, self.config.tech_decay);
+        }
+        self.position = position;
+    }
+}
+
+//a replay whose recording was cut short is still played up to the last frame that made it to disk
+pub fn read_replay(path: &Path) -> Result<Replay, ReplayError> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    let mut version = [0u8; 4];
+    file.read_exact(&mut magic).map_err(|_| ReplayError::NotAReplay)?;
+    if &magic != MAGIC {
+        return Err(ReplayError::NotAReplay);
+    }
+    file.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != REPLAY_VERSION {
+        return Err(ReplayError::Version(version));
+    }
+    let length = file.get_ref().metadata()?.len();
+    let mut stream = GzDecoder::new(file);
+    let header: ReplayHeader = bincode::DefaultOptions::new().deserialize_from(&mut stream).map_err(|e| ReplayError::Decode(e.to_string()))?;
+    if header.land.len() != header.config.width * header.config.height {
+        return Err(ReplayError::Decode(format!("{} cells on a {}x{} map", header.land.len(), header.config.width, header.config.height)));
+    }
+    let neighbors = Neighbors::new(&header.config.hex_grid());
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut empires = 0;
+    loop {
+        let frame: Frame = match bincode::DefaultOptions::new().deserialize_from(&mut stream) {
+            Ok(frame) => frame,
+            //failing once every byte has been read, even part way through a frame, is where the recording stopped.
+            //Anywhere before that the file is damaged.
+            Err(_) if stream.get_mut().stream_position()? >= length => break,
+            Err(e) => return Err(ReplayError::Decode(format!("frame {}: {}", frames.len(), e))),
+        };
+        if !frame.fits(&header.land, &neighbors, empires) {
+            return Err(ReplayError::Decode(format!("frame {} at tick {} doesn't fit the map", frames.len(), frame.tick)));
+        }
+        if let Change::Keyframe { empires: list, .. } = &frame.change {
+            empires = list.len();
+        }
+        //a keyframe going back in time is a rewind, the frames it replaces are dropped
+        if frames.last().is_some_and(|last| frame.tick <= last.tick) {
+            if !matches!(frame.change, Change::Keyframe { .. }) {
+                return Err(ReplayError::Decode(format!("frame {} goes back to tick {} without a keyframe", frames.len(), frame.tick)));
+            }
+            frames.truncate(frames.partition_point(|kept| kept.tick < frame.tick));
+        }
+        frames.push(frame);
+    }
+    if !frames.first().is_some_and(|frame| matches!(frame.change, Change::Keyframe { .. })) {
+        return Err(ReplayError::Decode("no frames".to_string()));
+    }
+    Ok(Replay { config: header.config, seed: header.seed, world: Some((header.grid, header.land)), frames, position: 0, packed: Vec::new() })
+}
+
+//stands in for setup when watching a replay
+pub fn spawn_replay(mut commands: Commands, mut replay: ResMut<Replay>, neighbors: Res<Neighbors>) {
+    let (grid, land) = replay.world.take().unwrap();
+    let mut map = MapData::new(replay.config.width, replay.config.height, land);
+    replay.seek(0, &mut map, &neighbors);
+    println!("Replaying ticks {} to {} of seed {}", replay.tick(), replay.last_tick(), replay.seed);
+    let mut game_data = GameData { max_strength: 0.0, max_age: 0, tick: replay.tick() };
+    game_data.measure(&map);
+    commands.insert_resource(game_data);
+    commands.insert_resource(map);
+    commands.insert_resource(grid);
+}
+
+//plays the replay at the same rate and with the same controls as the live simulation
+pub fn replay_step_system(mut replay: ResMut<Replay>, mut control: ResMut<SimControl>, mut map: ResMut<MapData>, mut game_data: ResMut<GameData>, neighbors: Res<Neighbors>) {
+    let ticks = match (control.step, control.paused) {
+        (true, _) => 1,
+        (false, true) => 0,
+        (false, false) => control.ticks_per_step,
+    };
+    control.step = false;
+    if ticks == 0 {
+        return;
+    }
+    for _ in 0..ticks {
+        replay.step(&mut map, &neighbors);
+    }
+    if replay.at_end() {
+        control.paused = true;
+    }
+    game_data.tick = replay.tick();
+    game_data.measure(&map);
+}
+
+#[derive(Component)]
+pub struct Timeline;
+
+#[derive(Component)]
+pub struct TimelineFill;
+
+#[derive(Component)]
+pub struct TimelineLabel;
+
+pub fn setup_timeline(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle { font_size: 14.0, color: Color::WHITE, ..Default::default() })
+            .with_style(Style { position_type: PositionType::Absolute, left: Val::Px(10.0), bottom: Val::Px(34.0), ..Default::default() }),
+        TimelineLabel,
+    ));
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    right: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    height: Val::Px(20.0),
+                    ..Default::default()
+                },
+                background_color: Color::srgba(0.15, 0.15, 0.15, 0.8).into(),
+                ..Default::default()
+            },
+            RelativeCursorPosition::default(),
+            Timeline,
+        ))
+        .with_children(|timeline| {
+            timeline.spawn((
+                NodeBundle {
+                    style: Style { width: Val::Percent(0.0), height: Val::Percent(100.0), ..Default::default() },
+                    background_color: Color::srgb(0.4, 0.4, 0.7).into(),
+                    ..Default::default()
+                },
+                TimelineFill,
+            ));
+        });
+}
+
+//click or drag on the timeline to jump there, comma steps back one tick
+pub fn scrub_system(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    timeline: Query<(&Interaction, &RelativeCursorPosition), With<Timeline>>,
+    mut replay: ResMut<Replay>,
+    mut control: ResMut<SimControl>,
+    mut map: ResMut<MapData>,
+    mut game_data: ResMut<GameData>,
+    neighbors: Res<Neighbors>,
+) {
+    let mut target = None;
+    if keyboard_input.just_pressed(KeyCode::Comma) {
+        control.paused = true;
+        target = Some(replay.position.saturating_sub(1));
+    }
+    if let Ok((Interaction::Pressed, cursor)) = timeline.get_single() {
+        if let Some(cursor) = cursor.normalized {
+            target = Some((cursor.x.clamp(0.0, 1.0) * (replay.frames.len() - 1) as f32).round() as usize);
+        }
+    }
+    match target {
+        Some(target) if target != replay.position => {
+            replay.seek(target, &mut map, &neighbors);
+            game_data.tick = replay.tick();
+            game_data.measure(&map);
+        }
+        _ => {}
+    }
+}
+
+pub fn draw_timeline_system(replay: Res<Replay>, mut fill: Query<&mut Style, With<TimelineFill>>, mut label: Query<&mut Text, With<TimelineLabel>>) {
+    if !replay.is_changed() {
+        return;
+    }
+    let progress = replay.position as f32 / (replay.frames.len() - 1).max(1) as f32;
+    if let Ok(mut style) = fill.get_single_mut() {
+        style.width = Val::Percent(progress * 100.0);
+    }
+    if let Ok(mut text) = label.get_single_mut() {
+        text.sections[0].value = format!(
+            "Replay: tick {} of {} (space plays/pauses, comma and period step, minus and equals change speed, drag to seek, need, send and boat need views aren't recorded)",
+            replay.tick(),
+            replay.last_tick()
+        );
+    }
+}
+
+#[derive(Component)]
+pub struct ReplayBoat;
+
+//boats are just sprites in a replay, one per boat in the frame on screen
+pub fn draw_replay_boats_system(
+    mut commands: Commands,
+    replay: Res<Replay>,
+    map: Res<MapData>,
+    config: Res<SimConfig>,
+    mut sprites: Query<(Entity, &mut Sprite, &mut Transform), With<ReplayBoat>>,
+) {
+    if !replay.is_changed() {
+        return;
+    }
+    let hex_grid = config.hex_grid();
+    let mut sprites = sprites.iter_mut();
+    for &(empire, index) in &replay.frames[replay.position].boats {
+        let empire = map.empire(empire);
+        let color = Color::hsla(empire.hue, empire.saturation, 0.5, 1.0);
+        let index = index as usize;
+        let translation = hex_grid.world_position((index % config.width, index / config.width)).extend(1.0);
+        match sprites.next() {
+            Some((_, mut sprite, mut transform)) => {
+                sprite.color = color;
+                transform.translation = translation;
+            }
+            None => {
+                commands.spawn((
+                    SpriteBundle {
+                        sprite: Sprite { color, custom_size: Some(Vec2::new(1.0, 1.0)), ..Default::default() },
+                        transform: Transform::from_translation(translation),
+                        ..Default::default()
+                    },
+                    ReplayBoat,
+                ));
+            }
+        }
+    }
+    for (entity, _, _) in sprites {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TECH_DECAY: f32 = 0.001;
+
+    //a 6x4 map with one water cell, split between three empires
+    fn world() -> (SimConfig, MapData, Neighbors) {
+        let mut config = SimConfig::default();
+        (config.width, config.height, config.tech_decay) = (6, 4, TECH_DECAY);
+        let land: Vec<bool> = (0..24).map(|index| index != 5).collect();
+        let mut map = MapData::new(6, 4, land);
+        map.empires = (0..3).map(|empire| Empire { hue: empire as f32 * 100.0, saturation: 0.5, aggression: 0.5, tech: 0.1, founded: 0 }).collect();
+        for index in (0..24).filter(|index| *index != 5) {
+            map.front.empire[index] = Some(EmpireId((index / 8) as u32));
+            map.front.strength[index] = index as f32 * 3.0 + 1.0;
+            map.front.age[index] = index as u32;
+        }
+        (config.clone(), map, Neighbors::new(&config.hex_grid()))
+    }
+
+    fn recorder(map: &MapData) -> ReplayRecorder {
+        ReplayRecorder { out: None, shown: MapData::new(map.width, map.height, map.land.clone()), packed: Vec::new(), tech_decay: TECH_DECAY, rewound: false }
+    }
+
+    //what a tick might do: everyone ages and tech decays, then a few cells change hands
+    fn tick(map: &mut MapData) {
+        advance(map, TECH_DECAY);
+        //taken by the neighbor at 8
+        map.front.empire[7] = Some(EmpireId(1));
+        map.front.age[7] = 1;
+        //a boat from empire 0, which has no cell next to it
+        map.front.empire[20] = Some(EmpireId(0));
+        map.front.age[20] = 1;
+        map.front.empire[12] = None;
+        map.front.age[12] = 0;
+        for strength in &mut map.front.strength {
+            *strength *= 1.3;
+        }
+        //things advance() can't predict
+        map.front.age[3] = 40;
+        map.empire_mut(EmpireId(2)).tech = 0.15;
+    }
+
+    fn encode_decode(frame: &Frame) -> Frame {
+        let bytes = bincode::DefaultOptions::new().serialize(frame).unwrap();
+        bincode::DefaultOptions::new().deserialize(&bytes).unwrap()
+    }
+
+    fn header(config: &SimConfig, map: &MapData) -> ReplayHeader {
+        ReplayHeader { config: config.clone(), seed: 1, grid: Grid { data: Vec::new() }, land: map.land.clone() }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("empires-{}-{}.replay", name, std::process::id()))
+    }
+
+    #[test]
+    fn strength_packs_within_a_step() {
+        for strength in [1.1, 1.5, 2.0, 10.0, 123.4, 5000.0, 40000.0] {
+            let unpacked = unpack(pack(strength));
+            assert!((unpacked / strength).ln().abs() <= 0.5 / STRENGTH_STEPS, "{} came back as {}", strength, unpacked);
+        }
+        assert_eq!(pack(0.5), 0);
+        assert_eq!(pack(1.0), 0);
+        assert_eq!(unpack(0), 0.0);
+        assert_eq!(pack(f32::MAX), 255);
+    }
+
+    #[test]
+    fn deltas_rebuild_the_recorded_map() {
+        let (_, mut map, neighbors) = world();
+        let mut recorder = recorder(&map);
+        let (mut viewer, mut packed) = (MapData::new(map.width, map.height, map.land.clone()), Vec::new());
+        for tick in 0..4 {
+            if tick > 0 {
+                self::tick(&mut map);
+            }
+            let change = match tick {
+                0 => recorder.keyframe(&map),
+                _ => recorder.delta(&map, &neighbors),
+            };
+            let frame = Frame { tick, boats: Vec::new(), change };
+            frame.apply(&mut recorder.shown, &mut recorder.packed, &neighbors, TECH_DECAY);
+            let decoded = encode_decode(&frame);
+            if let (1, Change::Delta { owners, ages, tech, .. }) = (tick, &decoded.change) {
+                let owners: Vec<String> = owners.iter().map(|(_, owner)| format!("{:?}", owner)).collect();
+                assert_eq!(owners, vec!["Neighbor(0)", "Abandoned", "Empire(EmpireId(0))"]);
+                //abandoned cells aren't predicted either
+                assert_eq!(ages, &vec![(3, 40), (12, 0)]);
+                assert_eq!(tech.len(), 1);
+            }
+            decoded.apply(&mut viewer, &mut packed, &neighbors, TECH_DECAY);
+
+            assert_eq!(viewer.front.empire, map.front.empire, "owners at tick {}", tick);
+            assert_eq!(viewer.front.age, map.front.age, "ages at tick {}", tick);
+            for (shown, actual) in viewer.empires.iter().zip(&map.empires) {
+                assert_eq!(shown.tech.to_bits(), actual.tech.to_bits(), "tech at tick {}", tick);
+            }
+            for (shown, actual) in viewer.front.strength.iter().zip(&map.front.strength) {
+                assert_eq!(pack(*shown), pack(*actual), "strength at tick {}", tick);
+            }
+        }
+    }
+
+    //record ticks 0 to 4, go back to tick 2 and record 3 and 4 again
+    fn record_with_rewind(path: &Path) -> MapData {
+        let (config, mut map, neighbors) = world();
+        let mut recorder = ReplayRecorder::create(path, &config).unwrap();
+        recorder.write(&header(&config, &map), false);
+        recorder.shown = MapData::new(map.width, map.height, map.land.clone());
+        let mut at_two = map.clone();
+        for tick in 0..=4 {
+            if tick > 0 {
+                self::tick(&mut map);
+            }
+            if tick == 2 {
+                at_two = map.clone();
+            }
+            recorder.record(tick, &map, &neighbors, Vec::new());
+        }
+        recorder.rewind();
+        map = at_two;
+        map.front.strength[0] = 99.0;
+        for tick in 3..=4 {
+            recorder.record(tick, &map, &neighbors, vec![(EmpireId(1), 0)]);
+        }
+        map
+    }
+
+    #[test]
+    fn a_rewind_replaces_the_frames_it_went_back_over() {
+        let path = temp_path("rewind");
+        let map = record_with_rewind(&path);
+        let replay = read_replay(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let ticks: Vec<u64> = replay.frames.iter().map(|frame| frame.tick).collect();
+        assert_eq!(ticks, vec![0, 1, 2, 3, 4]);
+        assert!(matches!(replay.frames[3].change, Change::Keyframe { .. }));
+        assert!(matches!(replay.frames[4].change, Change::Delta { .. }));
+        assert_eq!(replay.frames[4].boats, vec![(EmpireId(1), 0)]);
+        let mut replay = replay;
+        let (_, _, neighbors) = world();
+        let mut shown = MapData::new(map.width, map.height, map.land.clone());
+        replay.seek(4, &mut shown, &neighbors);
+        assert_eq!(shown.front.empire, map.front.empire);
+        assert_eq!(pack(shown.front.strength[0]), pack(99.0));
+    }
+
+    #[test]
+    fn a_cut_short_replay_plays_up_to_where_it_stops() {
+        let path = temp_path("cut");
+        record_with_rewind(&path);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 30]).unwrap();
+        let replay = read_replay(&path);
+        std::fs::remove_file(&path).unwrap();
+        let frames = replay.unwrap().frames.len();
+        assert!((1..5).contains(&frames), "{} frames", frames);
+    }
+
+    #[test]
+    fn corruption_before_the_end_is_an_error() {
+        let path = temp_path("corrupt");
+        let (config, map, neighbors) = world();
+        let mut recorder = ReplayRecorder::create(&path, &config).unwrap();
+        recorder.write(&header(&config, &map), false);
+        recorder.shown = MapData::new(map.width, map.height, map.land.clone());
+        recorder.record(0, &map, &neighbors, Vec::new());
+        //tick 1, no boats and a Change that doesn't exist
+        recorder.write(&(1u64, 0u8, 9u8), false);
+        recorder.record(2, &map, &neighbors, Vec::new());
+        drop(recorder);
+        let result = read_replay(&path);
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(ReplayError::Decode(e)) => assert!(e.starts_with("frame 1:"), "{}", e),
+            Err(e) => panic!("wrong error: {}", e),
+            Ok(replay) => panic!("read {} frames", replay.frames.len()),
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use crate::chronicle::Chronicle;
+use crate::events::{self, Territory};
+use crate::landmass::{Invasions, Landmasses};
+use crate::map::{CellColumns, EmpireId, MapData};
+use crate::replay::ReplayRecorder;
+use crate::rng::WorldSeed;
+use crate::sim::SimControl;
+use crate::stats::{StatsMark, StatsRecorder};
+use crate::{Boat, Cell, Cells, GameData};
+
+//how many ticks of branch comparison the panel shows at most
+const PANEL_ROWS: usize = 10;
+
+//the parts of the world that change while it runs, terrain and config stay put so they aren't copied
+struct Moment {
+    game_data: GameData,
+    map: MapData,
+    cells: Vec<Cell>,
+    boats: Vec<(Boat, Vec3)>,
+    //what the observers had seen by then, so they go back too
+    chronicle: Option<Chronicle>,
+    invasions: Invasions,
+    stats: Option<StatsMark>,
+}
+
+//a summary of one branch at one tick, for comparing how two histories went
+#[derive(Clone, Copy)]
+struct Sample {
+    tick: u64,
+    empires: usize,
+    largest: (EmpireId, u32),
+    mean_tech: f32,
+    boats: usize,
+}
+
+//one line of history. A rewind starts a new branch from the moment it went back to.
+struct Branch {
+    seed: u64,
+    //the branch it split from and the tick it split at
+    parent: Option<(usize, u64)>,
+    samples: Vec<Sample>,
+}
+
+//snapshots taken every `every` ticks, only the last `keep` are held on to
+#[derive(Resource)]
+pub struct Rewind {
+    every: u64,
+    keep: usize,
+    moments: VecDeque<Moment>,
+    branches: Vec<Branch>,
+    current: usize,
+    pub visible: bool,
+}
+
+impl Rewind {
+    pub fn new(every: u64, keep: usize, seed: u64) -> Self {
+        Rewind {
+            every,
+            keep,
+            moments: VecDeque::new(),
+            branches: vec![Branch { seed, parent: None, samples: Vec::new() }],
+            current: 0,
+            visible: false,
+        }
+    }
+
+    //the newest snapshot from before this tick. Anything newer belongs to the history being left behind and is dropped.
+    fn before(&mut self, tick: u64) -> Option<&Moment> {
+        while self.moments.back().is_some_and(|moment| moment.game_data.tick >= tick) {
+            self.moments.pop_back();
+        }
+        self.moments.back()
+    }
+
+    //the current branch next to the one it split from, at the ticks both have samples for
+    fn comparison(&self) -> Option<String> {
+        let branch = &self.branches[self.current];
+        let (parent, split) = branch.parent?;
+        let mut text = format!("Branch {} (seed {}) split from branch {} (seed {}) at tick {}\n", self.current, branch.seed, parent, self.branches[parent].seed, split);
+        let theirs = &self.branches[parent].samples;
+        let rows: Vec<(&Sample, &Sample)> = branch
+            .samples
+            .iter()
+            .filter(|sample| sample.tick > split)
+            .filter_map(|ours| theirs.iter().find(|theirs| theirs.tick == ours.tick).map(|theirs| (theirs, ours)))
+            .collect();
+        if rows.is_empty() {
+            text.push_str("No ticks to compare yet");
+        }
+        let describe = |sample: &Sample| {
+            format!("{} empires, largest {} ({} cells), tech {:.5}, {} boats", sample.empires, sample.largest.0, sample.largest.1, sample.mean_tech, sample.boats)
+        };
+        for (theirs, ours) in rows.iter().skip(rows.len().saturating_sub(PANEL_ROWS)) {
+            let _ = write!(text, "\ntick {}\n  branch {}: {}\n  branch {}: {}", ours.tick, parent, describe(theirs), self.current, describe(ours));
+        }
+        Some(text)
+    }
+}
+
+//runs at the end of every tick, and once at startup so the first moment can be gone back to
+#[allow(clippy::too_many_arguments)]
+pub fn remember_system(
+    mut rewind: ResMut<Rewind>,
+    game_data: Res<GameData>,
+    map: Res<MapData>,
+    cells: Res<Cells>,
+    territory: Res<Territory>,
+    boats: Query<(&Boat, &Transform)>,
+    chronicle: Option<Res<Chronicle>>,
+    landmasses: Res<Landmasses>,
+    stats: Option<ResMut<StatsRecorder>>,
+) {
+    if rewind.keep == 0 || !game_data.tick.is_multiple_of(rewind.every) {
+        return;
+    }
+    let alive: Vec<(EmpireId, u32)> = territory.0.iter().enumerate().filter(|(_, cells)| **cells > 0).map(|(index, cells)| (EmpireId(index as u32), *cells)).collect();
+    let sample = Sample {
+        tick: game_data.tick,
+        empires: alive.len(),
+        largest: alive.iter().copied().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0))).unwrap_or((EmpireId(0), 0)),
+        mean_tech: alive.iter().map(|(empire, _)| map.empire(*empire).tech).sum::<f32>() / alive.len().max(1) as f32,
+        boats: boats.iter().count(),
+    };
+    let current = rewind.current;
+    rewind.branches[current].samples.push(sample);
+
+    //back isn't needed, it's rebuilt from front when the moment is restored
+    let moment = Moment {
+        game_data: game_data.clone(),
+        map: MapData { back: CellColumns::default(), land: map.land.clone(), navigable: map.navigable.clone(), front: map.front.clone(), empires: map.empires.clone(), ..*map },
+        cells: cells.0.clone(),
+        boats: boats.iter().map(|(boat, transform)| (boat.clone(), transform.translation)).collect(),
+        chronicle: chronicle.map(|chronicle| chronicle.remember()),
+        invasions: landmasses.invasions(),
+        stats: stats.and_then(|mut stats| stats.mark().inspect_err(|e| eprintln!("Could not mark the statistics for rewinding: {}", e)).ok()),
+    };
+    rewind.moments.push_back(moment);
+    if rewind.moments.len() > rewind.keep {
+        rewind.moments.pop_front();
+    }
+}
+
+//R goes back to the last snapshot before now and carries on with the same seed, shift+R picks a new seed so history can
+//go differently. Either way the sim pauses on the moment it went back to.
+pub fn rewind_system(world: &mut World) {
+    let keyboard_input = world.resource::<ButtonInput<KeyCode>>();
+    if !keyboard_input.just_pressed(KeyCode::KeyR) {
+        return;
+    }
+    let reseed = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let tick = world.resource::<GameData>().tick;
+    let mut rewind = world.resource_mut::<Rewind>();
+    let Some(moment) = rewind.before(tick) else {
+        println!("Nothing to rewind to before tick {}", tick);
+        return;
+    };
+    let (game_data, mut map, cells, boats) = (moment.game_data.clone(), moment.map.clone(), moment.cells.clone(), moment.boats.clone());
+    let (chronicle, invasions, stats) = (moment.chronicle.as_ref().map(Chronicle::remember), moment.invasions.clone(), moment.stats.clone());
+    let seed = match reseed {
+        true => rand::random(),
+        false => rewind.branches[rewind.current].seed,
+    };
+    let (parent, split) = (rewind.current, game_data.tick);
+    let branch = rewind.branches.len();
+    let samples = rewind.branches[parent].samples.iter().copied().filter(|sample| sample.tick <= split).collect();
+    rewind.branches.push(Branch { seed, parent: Some((parent, split)), samples });
+    rewind.current = rewind.branches.len() - 1;
+    rewind.visible = true;
+    println!("Rewound to tick {}, now on branch {} with seed {}", split, rewind.current, seed);
+
+    map.restore_back();
+    let old_boats: Vec<Entity> = world.query_filtered::<Entity, With<Boat>>().iter(world).collect();
+    for entity in old_boats {
+        world.despawn(entity);
+    }
+    for (boat, translation) in boats {
+        world.spawn((boat, TransformBundle::from_transform(Transform::from_translation(translation))));
+    }
+    world.insert_resource(map);
+    world.insert_resource(Cells(cells));
+    world.insert_resource(game_data);
+    world.insert_resource(WorldSeed(seed));
+    world.run_system_once(events::count_territory_system);
+    if let Some(mut recorder) = world.get_resource_mut::<ReplayRecorder>() {
+        recorder.rewind();
+    }
+    world.resource_mut::<Landmasses>().restore(invasions);
+    if let (Some(mut recorder), Some(mark)) = (world.get_resource_mut::<StatsRecorder>(), stats) {
+        if let Err(e) = recorder.rewind(&mark) {
+            eprintln!("Could not rewind the statistics: {}", e);
+        }
+    }
+    if let (Some(mut current), Some(chronicle)) = (world.get_resource_mut::<Chronicle>(), chronicle) {
+        current.rewind(&chronicle, split, format!("History is rewound to tick {}, branch {} carries on with seed {}.", split, branch, seed));
+    }
+    world.resource_mut::<SimControl>().paused = true;
+    if let Ok(mut window) = world.query_filtered::<&mut Window, With<PrimaryWindow>>().get_single_mut(world) {
+        window.title = format!("Empires! (seed {})", seed);
+    }
+}
+
+#[derive(Component)]
+pub struct BranchPanel;
+
+pub fn setup_branch_panel(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle { font_size: 14.0, color: Color::WHITE, ..Default::default() })
+            .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.7))
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                top: Val::Px(50.0),
+                padding: UiRect::all(Val::Px(4.0)),
+                display: Display::None,
+                ..Default::default()
+            }),
+        BranchPanel,
+    ));
+}
+
+//B shows or hides the comparison, it opens by itself after a rewind
+pub fn draw_branch_panel_system(keyboard_input: Res<ButtonInput<KeyCode>>, mut rewind: ResMut<Rewind>, mut panel: Query<(&mut Text, &mut Style), With<BranchPanel>>) {
+    if keyboard_input.just_pressed(KeyCode::KeyB) {
+        rewind.visible = !rewind.visible;
+    }
+    let Ok((mut text, mut style)) = panel.get_single_mut() else {
+        return;
+    };
+    let comparison = rewind.comparison().filter(|_| rewind.visible);
+    style.display = if comparison.is_some() { Display::Flex } else { Display::None };
+    if let (Some(comparison), true) = (comparison, rewind.is_changed()) {
+        text.sections[0].value = format!("Branches (B to hide, R rewinds, shift+R rewinds with a new seed)\n{}", comparison);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{run_ticks, small_world};
+    use crate::{advance_tick_system, chronicle, sim, stats};
+
+    #[test]
+    fn rewinding_takes_the_observers_back_too() {
+        let path = std::env::temp_dir().join(format!("empires-rewind-{}.csv", std::process::id()));
+        let mut app = small_world(29);
+        app.insert_resource(Rewind::new(10, 5, 29));
+        app.insert_resource(Chronicle::new(None).unwrap());
+        app.insert_resource(StatsRecorder::create(&path, 5).unwrap());
+        app.insert_resource(ButtonInput::<KeyCode>::default());
+        app.add_systems(sim::SimTick, chronicle::chronicle_system.before(advance_tick_system));
+        app.add_systems(sim::SimTick, stats::record_stats_system.after(advance_tick_system));
+        app.add_systems(sim::SimTick, remember_system.after(advance_tick_system).after(stats::record_stats_system));
+        app.world_mut().run_system_once(stats::start_stats_system);
+        app.world_mut().run_system_once(remember_system);
+        run_ticks(&mut app, 25);
+        let at_20 = app.world().resource::<Rewind>().moments.back().unwrap();
+        let (feed_at_20, invasions_at_20) = (at_20.chronicle.as_ref().unwrap().feed.len(), at_20.invasions.clone());
+        //boats have set out and landed since
+        assert!(app.world().resource::<Landmasses>().invasions() != invasions_at_20);
+
+        app.world_mut().resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyR);
+        app.world_mut().run_system_once(rewind_system);
+        assert_eq!(app.world().resource::<GameData>().tick, 20);
+        assert!(app.world().resource::<Landmasses>().invasions() == invasions_at_20);
+        let chronicle = app.world().resource::<Chronicle>();
+        assert_eq!(chronicle.feed.len(), feed_at_20 + 1);
+        assert!(chronicle.feed.back().unwrap().1.starts_with("History is rewound to tick 20"));
+
+        app.world_mut().resource_mut::<ButtonInput<KeyCode>>().release(KeyCode::KeyR);
+        run_ticks(&mut app, 10);
+        drop(app);
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        //every empire's sample once, in order, even though ticks 21 to 25 were run twice
+        let rows: Vec<(u64, u32)> = written
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let mut fields = line.split(',').map(|field| field.parse::<u64>().unwrap());
+                (fields.next().unwrap(), fields.next().unwrap() as u32)
+            })
+            .collect();
+        assert!(rows.windows(2).all(|pair| pair[0] < pair[1]), "rows out of order or repeated");
+        let mut ticks: Vec<u64> = rows.iter().map(|(tick, _)| *tick).collect();
+        ticks.dedup();
+        assert_eq!(ticks, vec![5, 10, 15, 20, 25, 30]);
+    }
+}
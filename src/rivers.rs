@@ -0,0 +1,102 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::config::SimConfig;
+use crate::map::{Neighbors, NO_CELL};
+use crate::water::is_land;
+
+//rain falls on every land cell in proportion to its moisture and runs downhill to the sea. A cell's flow is all the
+//rain that passes through it, in cells' worth of rain, and it's a river once that's more than config.river_threshold.
+//Everything is indexed y * width + x.
+
+//the cell each land cell drains into, found by flooding inland from the coast lowest cell first (a priority flood).
+//Water that pools in a pit spills over the lowest rim, so every drop finds the sea. Cells are returned in the order
+//they were reached, so each one comes after the cell it drains into.
+fn drainage(elevation: &[f32], neighbors: &Neighbors, config: &SimConfig) -> (Vec<u32>, Vec<usize>) {
+    let mut downhill = vec![NO_CELL; elevation.len()];
+    let mut reached: Vec<bool> = elevation.iter().map(|&elevation| !is_land(elevation, config)).collect();
+    let mut order = Vec::new();
+    //elevations are never negative, so their bits sort the same way they do
+    let mut frontier: BinaryHeap<_> = (0..elevation.len()).filter(|&index| reached[index]).map(|index| Reverse((config.ocean_cutoff.to_bits(), index))).collect();
+    while let Some(Reverse((level, index))) = frontier.pop() {
+        for &neighbor in &neighbors.0[index] {
+            if neighbor == NO_CELL || reached[neighbor as usize] {
+                continue;
+            }
+            let neighbor = neighbor as usize;
+            reached[neighbor] = true;
+            downhill[neighbor] = index as u32;
+            order.push(neighbor);
+            //a pit fills up to the level it spills at
+            frontier.push(Reverse((level.max(elevation[neighbor].to_bits()), neighbor)));
+        }
+    }
+    (downhill, order)
+}
+
+//the flow through every cell, 0 in the ocean. A map without ocean has nowhere to drain and no rivers.
+pub fn flow(elevation: &[f32], moisture: &[f32], config: &SimConfig) -> Vec<f32> {
+    let neighbors = Neighbors::new(&config.hex_grid());
+    let (downhill, order) = drainage(elevation, &neighbors, config);
+    let mut flow = vec![0.0; elevation.len()];
+    //from the springs down, every cell passes what it has on to the next
+    for &index in order.iter().rev() {
+        flow[index] += moisture[index];
+        let next = downhill[index] as usize;
+        if is_land(elevation[next], config) {
+            flow[next] += flow[index];
+        }
+    }
+    flow
+}
+
+//how big the river on a cell is: 0 for no river, 0.5 for one that just became a river, approaching 1 for the largest
+pub fn river_size(flow: f32, config: &SimConfig) -> f32 {
+    if flow < config.river_threshold {
+        0.0
+    } else {
+        1.0 - config.river_threshold / (2.0 * flow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flow_runs_downhill_into_the_sea_or_a_lake() {
+        let mut config = SimConfig::default();
+        (config.width, config.height, config.ocean_cutoff) = (10, 4, 0.5);
+        let width = config.width;
+        //the sea is column 0, the land climbs away from it on both sides (the map wraps) up to a ridge at column 5,
+        //and there's a one cell lake on the slope
+        let lake = width + 3;
+        let mut elevation: Vec<f32> = (0..width * config.height).map(|index| 0.4 + 0.1 * (index % width).min(width - index % width) as f32).collect();
+        elevation[lake] = 0.0;
+        let moisture = vec![1.0; elevation.len()];
+        let water = |index: usize| !is_land(elevation[index], &config);
+
+        let (downhill, _) = drainage(&elevation, &Neighbors::new(&config.hex_grid()), &config);
+        let flow = flow(&elevation, &moisture, &config);
+        let mut into_water = 0.0;
+        for index in 0..elevation.len() {
+            if water(index) {
+                assert_eq!(flow[index], 0.0);
+                continue;
+            }
+            let next = downhill[index] as usize;
+            assert!(elevation[next] <= elevation[index], "cell {} drains uphill", index);
+            //everything upstream is added on the way down, and none of it goes past the water
+            let upstream: f32 = (0..elevation.len()).filter(|&other| downhill[other] as usize == index).map(|other| flow[other]).sum();
+            assert_eq!(flow[index], 1.0 + upstream);
+            match water(next) {
+                true => into_water += flow[index],
+                false => assert!(flow[next] > flow[index]),
+            }
+        }
+        let land = (0..elevation.len()).filter(|&index| !water(index)).count();
+        assert_eq!(into_water, land as f32);
+        assert!(downhill.iter().any(|&next| next as usize == lake));
+        assert!(flow.iter().any(|&flow| flow > 1.0));
+    }
+}
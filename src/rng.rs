@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+//the seed every random decision in a run is derived from. Same seed + same config = same run.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct WorldSeed(pub u64);
+
+//each part of the simulation draws from its own stream so adding a roll in one place doesn't shift every other.
+#[derive(Clone, Copy)]
+pub enum RngStream {
+    Terrain = 1,
+    Empires = 2,
+    Push = 3,
+    Boat = 4,
+    Tech = 5,
+    Tectonics = 6,
+    Names = 7,
+}
+
+//splitmix64 finalizer, spreads nearby inputs (neighboring cells, consecutive ticks) across the whole seed space.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+//an rng that only depends on its inputs, not on which thread asks for it or in what order.
+//id is whatever identifies the caller within a tick: a cell index, a boat id, ...
+pub fn rng_for(seed: WorldSeed, stream: RngStream, tick: u64, id: u64) -> ChaCha8Rng {
+    ChaCha8Rng::seed_from_u64(stream_seed(seed, stream, tick, id))
+}
+
+fn stream_seed(seed: WorldSeed, stream: RngStream, tick: u64, id: u64) -> u64 {
+    mix(mix(mix(seed.0 ^ stream as u64) ^ tick) ^ id)
+}
+
+//same numbers as rng_for, but the ChaCha state is only built on the first roll.
+//Most cells don't roll anything in a push, and setting up a ChaCha8Rng for each of them costs more than the push itself.
+pub struct LazyRng {
+    seed: u64,
+    rng: Option<ChaCha8Rng>,
+}
+
+impl LazyRng {
+    fn rng(&mut self) -> &mut ChaCha8Rng {
+        let seed = self.seed;
+        self.rng.get_or_insert_with(|| ChaCha8Rng::seed_from_u64(seed))
+    }
+}
+
+impl RngCore for LazyRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng().next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng().next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng().fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng().try_fill_bytes(dest)
+    }
+}
+
+pub fn lazy_rng_for(seed: WorldSeed, stream: RngStream, tick: u64, id: u64) -> LazyRng {
+    LazyRng { seed: stream_seed(seed, stream, tick, id), rng: None }
+}
@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::SimConfig;
+use crate::landmass::{Invasions, Landmasses};
+use crate::map::MapData;
+use crate::rng::WorldSeed;
+use crate::{Boat, Cell, Cells, GameData, Grid};
+
+//bump whenever anything in WorldSnapshot (or the types inside it) changes shape.
+pub const SAVE_VERSION: u32 = 8;
+const MAGIC: &[u8; 8] = b"EMPIRES\0";
+
+//everything needed to rebuild the ECS world and continue the run exactly where it was saved.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WorldSnapshot {
+    pub config: SimConfig,
+    pub seed: u64,
+    pub game_data: GameData,
+    pub grid: Grid,
+    pub map: MapData,
+    pub cells: Vec<Cell>,
+    pub boats: Vec<(Boat, [f32; 3])>,
+    pub invasions: Invasions,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    NotASave,
+    Version(u32),
+    Decode(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "{}", e),
+            SaveError::NotASave => write!(f, "not an empires save file"),
+            SaveError::Version(v) => write!(f, "save file version {} is not supported (expected {})", v, SAVE_VERSION),
+            SaveError::Decode(e) => write!(f, "corrupt save file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+//file layout: magic, version as little endian u32, then the gzipped bincode snapshot.
+pub fn write_snapshot(path: &Path, snapshot: &WorldSnapshot) -> Result<(), SaveError> {
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(MAGIC)?;
+    file.write_all(&SAVE_VERSION.to_le_bytes())?;
+    let mut encoder = GzEncoder::new(file, Compression::fast());
+    bincode::serialize_into(&mut encoder, snapshot).map_err(|e| SaveError::Decode(e.to_string()))?;
+    encoder.finish()?.flush()?;
+    Ok(())
+}
+
+pub fn read_snapshot(path: &Path) -> Result<WorldSnapshot, SaveError> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    let mut version = [0u8; 4];
+    file.read_exact(&mut magic).map_err(|_| SaveError::NotASave)?;
+    if &magic != MAGIC {
+        return Err(SaveError::NotASave);
+    }
+    file.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != SAVE_VERSION {
+        return Err(SaveError::Version(version));
+    }
+    bincode::deserialize_from(GzDecoder::new(file)).map_err(|e| SaveError::Decode(e.to_string()))
+}
+
+//a snapshot read from --load, consumed by spawn_snapshot at startup.
+#[derive(Resource)]
+pub struct PendingLoad(pub Option<WorldSnapshot>);
+
+//saves are only written between ticks so a loaded world resumes with a full pull + push cycle.
+#[derive(Resource, Default)]
+pub struct SaveRequest {
+    pub pending: bool,
+    pub at_tick: Option<u64>,
+    pub path: Option<PathBuf>,
+}
+
+pub fn spawn_snapshot(mut commands: Commands, mut pending: ResMut<PendingLoad>) {
+    let Some(mut snapshot) = pending.0.take() else {
+        return;
+    };
+    let land = snapshot.map.land.iter().filter(|land| **land).count();
+    println!("Loaded world at tick {} with {} cells and {} boats", snapshot.game_data.tick, land, snapshot.boats.len());
+    snapshot.map.restore_back();
+    commands.insert_resource(Cells(snapshot.cells));
+    for (boat, translation) in snapshot.boats {
+        commands.spawn((boat, TransformBundle::from_transform(Transform::from_translation(Vec3::from_array(translation)))));
+    }
+    commands.insert_resource(snapshot.grid);
+    commands.insert_resource(snapshot.map);
+    commands.insert_resource(snapshot.game_data);
+    //landmasses are labelled again from the map, see label_landmasses_system
+    commands.insert_resource(snapshot.invasions);
+    commands.remove_resource::<PendingLoad>();
+}
+
+pub fn request_save_system(keyboard_input: Res<ButtonInput<KeyCode>>, mut request: ResMut<SaveRequest>) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        request.pending = true;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn save_system(
+    mut request: ResMut<SaveRequest>,
+    config: Res<SimConfig>,
+    seed: Res<WorldSeed>,
+    game_data: Res<GameData>,
+    grid: Res<Grid>,
+    map: Res<MapData>,
+    cells: Res<Cells>,
+    landmasses: Res<Landmasses>,
+    boats: Query<(&Boat, &Transform)>,
+) {
+    let scheduled = request.at_tick == Some(game_data.tick);
+    if !request.pending && !scheduled {
+        return;
+    }
+    request.pending = false;
+    //a scheduled save is written once, not on every frame spent at that tick while paused or after rewinding to it
+    if scheduled {
+        request.at_tick = None;
+    }
+
+    let snapshot = WorldSnapshot {
+        config: config.clone(),
+        seed: seed.0,
+        game_data: game_data.clone(),
+        grid: grid.clone(),
+        map: map.clone(),
+        cells: cells.0.clone(),
+        boats: boats.iter().map(|(boat, transform)| (boat.clone(), transform.translation.to_array())).collect(),
+        invasions: landmasses.invasions(),
+    };
+    let path = match (&request.path, scheduled) {
+        (Some(path), true) => path.clone(),
+        _ => PathBuf::from(format!("saves/empires-{}-t{}.sav", seed.0, game_data.tick)),
+    };
+    match write_snapshot(&path, &snapshot) {
+        Ok(()) => println!("Saved tick {} to {}", game_data.tick, path.display()),
+        Err(e) => eprintln!("Could not save to {}: {}", path.display(), e),
+    }
+}
+
+//a scheduled save is cleared once written, one still pending when the app closes was never reached
+pub fn missed_save_system(request: Res<SaveRequest>, game_data: Res<GameData>, mut exit: EventReader<AppExit>) {
+    if exit.read().next().is_none() {
+        return;
+    }
+    if let Some(tick) = request.at_tick {
+        eprintln!("The save scheduled for tick {} was never written, the run ended at tick {}", tick, game_data.tick);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Neighbors;
+    use crate::tests::{loaded_world, run_ticks, small_world, world_state};
+
+    #[test]
+    fn loaded_save_continues_like_the_original() {
+        let path = std::env::temp_dir().join(format!("empires-round-trip-{}.sav", std::process::id()));
+        let mut original = small_world(23);
+        original.insert_resource(SaveRequest { pending: false, at_tick: Some(20), path: Some(path.clone()) });
+        original.add_systems(Last, save_system);
+        run_ticks(&mut original, 20);
+        original.world_mut().run_schedule(Last);
+        let snapshot = read_snapshot(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        //the request is used up, another frame at the same tick doesn't save again
+        assert_eq!(original.world().resource::<SaveRequest>().at_tick, None);
+        original.world_mut().run_schedule(Last);
+        assert!(!path.exists());
+
+        let invasions = snapshot.invasions.clone();
+        let mut loaded = loaded_world(snapshot);
+        assert_eq!(loaded.world().resource::<GameData>().tick, 20);
+        //boats at sea and landmasses already invaded are carried over, not started afresh
+        let world = loaded.world();
+        let fresh = Landmasses::label(&world.resource::<MapData>().land, world.resource::<Neighbors>(), world.resource::<SimConfig>(), *world.resource::<WorldSeed>());
+        assert_ne!(fresh.invasions(), invasions, "nothing was known at the save, so it doesn't test invasions");
+        assert_eq!(world.resource::<Landmasses>().invasions(), invasions);
+        run_ticks(&mut original, 30);
+        run_ticks(&mut loaded, 30);
+        assert!(world_state(&mut original) == world_state(&mut loaded), "the loaded world ended differently from the one that was saved");
+        assert_eq!(loaded.world().resource::<Landmasses>().invasions(), original.world().resource::<Landmasses>().invasions());
+    }
+}
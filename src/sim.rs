@@ -0,0 +1,63 @@
+use bevy::ecs::schedule::ScheduleLabel;
+use bevy::prelude::*;
+
+use crate::save::SaveRequest;
+use crate::GameData;
+
+//one full simulation tick: boats, pull, empires, push. Run from run_sim_ticks instead of once per frame.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SimTick;
+
+//how often run_sim_ticks is called when there is a window
+pub const STEPS_PER_SECOND: f64 = 30.0;
+const MAX_TICKS_PER_STEP: u32 = 64;
+
+#[derive(Resource)]
+pub struct SimControl {
+    pub paused: bool,
+    //run exactly one tick on the next step, even while paused
+    pub step: bool,
+    pub ticks_per_step: u32,
+}
+
+impl Default for SimControl {
+    fn default() -> Self {
+        SimControl { paused: false, step: false, ticks_per_step: 1 }
+    }
+}
+
+//windowed this runs in FixedUpdate so the simulation speed doesn't depend on the frame rate, headless it runs every frame.
+pub fn run_sim_ticks(world: &mut World) {
+    let mut control = world.resource_mut::<SimControl>();
+    let ticks = match (control.step, control.paused) {
+        (true, _) => 1,
+        (false, true) => 0,
+        (false, false) => control.ticks_per_step,
+    };
+    control.step = false;
+    for _ in 0..ticks {
+        world.run_schedule(SimTick);
+        //a scheduled save has to see the world at exactly that tick, so stop there for this step
+        let tick = world.resource::<GameData>().tick;
+        if world.resource::<SaveRequest>().at_tick == Some(tick) {
+            break;
+        }
+    }
+}
+
+//space pauses, period steps one tick (and pauses), minus and equals halve or double the ticks run per step.
+pub fn sim_control_system(keyboard_input: Res<ButtonInput<KeyCode>>, mut control: ResMut<SimControl>) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        control.paused = !control.paused;
+    }
+    if keyboard_input.just_pressed(KeyCode::Period) {
+        control.paused = true;
+        control.step = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Equal) {
+        control.ticks_per_step = (control.ticks_per_step * 2).min(MAX_TICKS_PER_STEP);
+    }
+    if keyboard_input.just_pressed(KeyCode::Minus) {
+        control.ticks_per_step = (control.ticks_per_step / 2).max(1);
+    }
+}
@@ -0,0 +1,258 @@
+use bevy::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::events::{BoatLanded, BoatLaunched, CellAbandoned, CellCaptured};
+use crate::map::{EmpireId, MapData, Neighbors, NO_CELL};
+use crate::GameData;
+
+pub enum StatsFormat {
+    Csv,
+    JsonLines,
+}
+
+const COLUMNS: [&str; 12] = [
+    "tick",
+    "empire",
+    "cells",
+    "strength",
+    "mean_strength",
+    "tech",
+    "aggression",
+    "coastline",
+    "boats_launched",
+    "boats_landed",
+    "cells_gained",
+    "cells_lost",
+];
+
+//one field of a row
+enum Value {
+    Count(u64),
+    Number(f32),
+}
+
+impl Value {
+    //JSON has no NaN or infinity, so those are written as null there
+    fn format(&self, format: &StatsFormat) -> String {
+        match (self, format) {
+            (Value::Count(value), _) => value.to_string(),
+            (Value::Number(value), StatsFormat::JsonLines) if !value.is_finite() => "null".to_string(),
+            (Value::Number(value), _) => value.to_string(),
+        }
+    }
+}
+
+//what happened to one empire since the last sample
+#[derive(Clone, Copy, Default)]
+struct Counters {
+    boats_launched: u32,
+    boats_landed: u32,
+    cells_gained: u32,
+    cells_lost: u32,
+}
+
+//how far the file had got and what had been counted since the last sample, so a rewind can drop what came after
+#[derive(Clone)]
+pub struct StatsMark {
+    position: u64,
+    counters: Vec<Counters>,
+}
+
+//writes one row per empire every `every` ticks. Boats and ownership changes are counted from the simulation events.
+#[derive(Resource)]
+pub struct StatsRecorder {
+    every: u64,
+    format: StatsFormat,
+    out: BufWriter<File>,
+    counters: Vec<Counters>,
+    //land cells next to the ocean, filled in once the world exists
+    coast: Vec<bool>,
+}
+
+impl StatsRecorder {
+    //.csv writes CSV with a header, anything else is JSON Lines
+    pub fn create(path: &Path, every: u64) -> std::io::Result<Self> {
+        let format = match path.extension().is_some_and(|ext| ext == "csv") {
+            true => StatsFormat::Csv,
+            false => StatsFormat::JsonLines,
+        };
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let mut out = BufWriter::new(File::create(path)?);
+        if let StatsFormat::Csv = format {
+            writeln!(out, "{}", COLUMNS.join(","))?;
+        }
+        Ok(StatsRecorder { every, format, out, counters: Vec::new(), coast: Vec::new() })
+    }
+
+    pub fn mark(&mut self) -> std::io::Result<StatsMark> {
+        self.out.flush()?;
+        Ok(StatsMark { position: self.out.get_mut().stream_position()?, counters: self.counters.clone() })
+    }
+
+    //forget every row written after the mark, they belong to a history that was rewound
+    pub fn rewind(&mut self, mark: &StatsMark) -> std::io::Result<()> {
+        self.out.flush()?;
+        let file = self.out.get_mut();
+        file.set_len(mark.position)?;
+        file.seek(SeekFrom::Start(mark.position))?;
+        self.counters.clone_from(&mark.counters);
+        Ok(())
+    }
+
+    fn write_row(&mut self, values: [Value; 12]) -> std::io::Result<()> {
+        let values: Vec<String> = values.iter().map(|value| value.format(&self.format)).collect();
+        match self.format {
+            StatsFormat::Csv => writeln!(self.out, "{}", values.join(",")),
+            StatsFormat::JsonLines => {
+                let fields: Vec<String> = COLUMNS.iter().zip(&values).map(|(key, value)| format!("\"{}\":{}", key, value)).collect();
+                writeln!(self.out, "{{{}}}", fields.join(","))
+            }
+        }
+    }
+
+    fn sample(&mut self, tick: u64, cell_map: &MapData) -> std::io::Result<()> {
+        let empires = cell_map.empires.len();
+        self.counters.resize(empires.max(self.counters.len()), Counters::default());
+        let (mut cells, mut strength, mut coastline) = (vec![0u32; empires], vec![0.0f32; empires], vec![0u32; empires]);
+        for (index, empire) in cell_map.front.empire.iter().enumerate() {
+            if let Some(empire) = empire {
+                cells[empire.index()] += 1;
+                strength[empire.index()] += cell_map.front.strength[index];
+                coastline[empire.index()] += self.coast[index] as u32;
+            }
+        }
+        for index in 0..empires {
+            let counters = std::mem::take(&mut self.counters[index]);
+            //empires that are long gone would only add rows of zeros
+            if cells[index] == 0 && counters.cells_lost == 0 {
+                continue;
+            }
+            let empire = &cell_map.empires[index];
+            self.write_row([
+                Value::Count(tick),
+                Value::Count(index as u64),
+                Value::Count(cells[index] as u64),
+                Value::Number(strength[index]),
+                Value::Number(strength[index] / cells[index].max(1) as f32),
+                Value::Number(empire.tech),
+                Value::Number(empire.aggression),
+                Value::Count(coastline[index] as u64),
+                Value::Count(counters.boats_launched as u64),
+                Value::Count(counters.boats_landed as u64),
+                Value::Count(counters.cells_gained as u64),
+                Value::Count(counters.cells_lost as u64),
+            ])?;
+        }
+        self.out.flush()
+    }
+}
+
+//empires can be founded after the recorder starts, so the counters grow on demand
+fn counters_for(counters: &mut Vec<Counters>, empire: EmpireId) -> &mut Counters {
+    if counters.len() <= empire.index() {
+        counters.resize(empire.index() + 1, Counters::default());
+    }
+    &mut counters[empire.index()]
+}
+
+//the world is built (or loaded) during Startup, so this runs after it
+pub fn start_stats_system(mut recorder: ResMut<StatsRecorder>, cell_map: Res<MapData>, neighbors: Res<Neighbors>) {
+    recorder.coast = neighbors.0.iter().enumerate().map(|(index, row)| {
+        cell_map.land[index] && row.iter().any(|&neighbor| neighbor != NO_CELL && cell_map.navigable[neighbor as usize])
+    }).collect();
+}
+
+//runs at the end of every tick when --stats is given
+pub fn record_stats_system(
+    mut recorder: ResMut<StatsRecorder>,
+    cell_map: Res<MapData>,
+    game_data: Res<GameData>,
+    mut captured: EventReader<CellCaptured>,
+    mut abandoned: EventReader<CellAbandoned>,
+    mut launched: EventReader<BoatLaunched>,
+    mut landed: EventReader<BoatLanded>,
+) {
+    let counters = &mut recorder.counters;
+    for event in captured.read() {
+        counters_for(counters, event.to).cells_gained += 1;
+        if let Some(from) = event.from {
+            counters_for(counters, from).cells_lost += 1;
+        }
+    }
+    for event in abandoned.read() {
+        counters_for(counters, event.empire).cells_lost += 1;
+    }
+    for event in launched.read() {
+        counters_for(counters, event.empire).boats_launched += 1;
+    }
+    for event in landed.read() {
+        counters_for(counters, event.empire).boats_landed += 1;
+    }
+
+    if game_data.tick.is_multiple_of(recorder.every) {
+        if let Err(e) = recorder.sample(game_data.tick, &cell_map) {
+            eprintln!("Could not write statistics: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Empire;
+
+    //a 3x2 map: two cells of Empire 0 (one on the coast), one of Empire 1 and three of ocean
+    fn map(strength: f32) -> MapData {
+        let mut map = MapData::new(3, 2, vec![true, true, true, false, false, false]);
+        map.empires = vec![Empire { hue: 0.0, saturation: 0.5, aggression: 0.25, tech: 0.125, founded: 0 }; 2];
+        map.front.empire = vec![Some(EmpireId(0)), Some(EmpireId(0)), Some(EmpireId(1)), None, None, None];
+        map.front.strength = vec![1.5, strength, 4.0, 0.0, 0.0, 0.0];
+        map
+    }
+
+    fn record(name: &str, strength: f32) -> Vec<String> {
+        let path = std::env::temp_dir().join(format!("empires-stats-{}-{}", std::process::id(), name));
+        let mut recorder = StatsRecorder::create(&path, 10).unwrap();
+        recorder.coast = vec![true, false, false, false, false, false];
+        counters_for(&mut recorder.counters, EmpireId(1)).boats_launched = 3;
+        recorder.sample(20, &map(strength)).unwrap();
+        drop(recorder);
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        written.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn csv_has_a_header_and_a_row_per_empire() {
+        assert_eq!(
+            record("rows.csv", 2.5),
+            vec![
+                COLUMNS.join(","),
+                "20,0,2,4,2,0.125,0.25,1,0,0,0,0".to_string(),
+                "20,1,1,4,4,0.125,0.25,0,3,0,0,0".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_lines_are_one_object_per_empire() {
+        assert_eq!(
+            record("rows.jsonl", 2.5)[0],
+            r#"{"tick":20,"empire":0,"cells":2,"strength":4,"mean_strength":2,"tech":0.125,"aggression":0.25,"coastline":1,"boats_launched":0,"boats_landed":0,"cells_gained":0,"cells_lost":0}"#
+        );
+    }
+
+    #[test]
+    fn json_lines_write_non_finite_numbers_as_null() {
+        let rows = record("nan.jsonl", f32::NAN);
+        assert!(rows[0].contains(r#""strength":null,"mean_strength":null,"#), "{}", rows[0]);
+        //the other empire is unaffected
+        assert!(rows[1].contains(r#""strength":4,"#), "{}", rows[1]);
+    }
+}
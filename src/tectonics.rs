@@ -0,0 +1,255 @@
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::config::SimConfig;
+use crate::hex::Direction;
+use crate::map::{Neighbors, NO_CELL};
+use crate::rng::{rng_for, RngStream, WorldSeed};
+use crate::water::is_land;
+
+//elevation built the way real continents are: the map is split into drifting plates, land rises where they push
+//together and splits where they pull apart, then rain wears the result down. Everything is indexed y * width + x.
+
+//how many plates are continental, the rest sit at the bottom of the sea
+const CONTINENTAL_CHANCE: f64 = 0.4;
+//collisions and rifts are felt this many cells away from the boundary, fading with distance
+const BOUNDARY_REACH: u32 = 8;
+//how much noise is laid over each plate so coasts aren't straight lines
+const DETAIL: f32 = 1.0;
+//how far boundaries raise or lower the land, per unit of plate speed towards (or away from) each other
+const MOUNTAINS: f32 = 0.4;
+const TRENCHES: f32 = 0.2;
+const ISLAND_ARCS: f32 = 0.25;
+const RIFTS: f32 = 0.15;
+const RIDGES: f32 = 0.08;
+
+//droplet erosion. Each droplet runs downhill picking up soil while it's fast and dropping it when it slows or reaches the sea.
+const DROPLET_STEPS: usize = 64;
+const CAPACITY: f32 = 2.0;
+const EROSION: f32 = 0.1;
+const DEPOSITION: f32 = 0.3;
+const EVAPORATION: f32 = 0.02;
+const SLUMP: f32 = 0.5;
+
+struct Plate {
+    continental: bool,
+    base: f32,
+    velocity: (f32, f32),
+}
+
+//a unit step towards each neighbor, in hex::Direction::ALL order
+fn direction_vectors() -> [(f32, f32); 6] {
+    Direction::ALL.map(|direction| {
+        let angle = direction.angle().to_radians();
+        (angle.cos(), angle.sin())
+    })
+}
+
+//grow every plate from a random cell at once. Crossing high detail costs more, which bends the borders
+//along the noise instead of leaving straight Voronoi edges.
+fn assign_plates(neighbors: &Neighbors, count: usize, detail: &[f32], rng: &mut impl Rng) -> Vec<usize> {
+    let cells = neighbors.0.len();
+    let mut plate = vec![usize::MAX; cells];
+    let cost: Vec<u32> = detail.iter().map(|detail| 1 + (detail.powi(4) * 64.0) as u32).collect();
+    let mut frontier = BinaryHeap::new();
+    for id in 0..count {
+        frontier.push((Reverse(0), rng.gen_range(0..cells), id));
+    }
+    while let Some((Reverse(distance), index, id)) = frontier.pop() {
+        if plate[index] != usize::MAX {
+            continue;
+        }
+        plate[index] = id;
+        for &neighbor in &neighbors.0[index] {
+            if neighbor != NO_CELL && plate[neighbor as usize] == usize::MAX {
+                frontier.push((Reverse(distance + cost[neighbor as usize]), neighbor as usize, id));
+            }
+        }
+    }
+    plate
+}
+
+//how much a boundary cell is raised (or lowered) by the plates meeting there
+fn boundary_effect(index: usize, plate: &[usize], plates: &[Plate], neighbors: &Neighbors) -> Option<f32> {
+    let directions = direction_vectors();
+    let own = &plates[plate[index]];
+    let (mut total, mut count) = (0.0, 0);
+    for (direction, &neighbor) in neighbors.0[index].iter().enumerate() {
+        if neighbor == NO_CELL || plate[neighbor as usize] == plate[index] {
+            continue;
+        }
+        let other = &plates[plate[neighbor as usize]];
+        //positive when the plates move towards each other across this edge
+        let (dx, dy) = directions[direction];
+        let closing = (own.velocity.0 - other.velocity.0) * dx + (own.velocity.1 - other.velocity.1) * dy;
+        total += match (closing > 0.0, own.continental, other.continental) {
+            (true, true, _) => MOUNTAINS * closing,
+            //the ocean floor dives under the continent
+            (true, false, true) => -TRENCHES * closing,
+            (true, false, false) => ISLAND_ARCS * closing,
+            (false, true, _) => RIFTS * closing,
+            (false, false, _) => -RIDGES * closing,
+        };
+        count += 1;
+    }
+    (count > 0).then(|| total / count as f32)
+}
+
+fn erode(elevation: &mut [f32], neighbors: &Neighbors, droplets: usize, config: &SimConfig, rng: &mut impl Rng) {
+    let ocean_cutoff = config.ocean_cutoff;
+    for _ in 0..droplets {
+        let mut index = rng.gen_range(0..elevation.len());
+        let (mut water, mut sediment) = (1.0, 0.0);
+        for _ in 0..DROPLET_STEPS {
+            if !is_land(elevation[index], config) {
+                //reached the sea, whatever it carries builds up the sea floor without making new islands
+                elevation[index] = (elevation[index] + sediment).min(ocean_cutoff - f32::EPSILON).max(elevation[index]);
+                break;
+            }
+            let lowest = neighbors.0[index]
+                .iter()
+                .filter(|&&neighbor| neighbor != NO_CELL)
+                .map(|&neighbor| neighbor as usize)
+                .min_by(|a, b| elevation[*a].total_cmp(&elevation[*b]));
+            let Some(lowest) = lowest else {
+                break;
+            };
+            let drop = elevation[index] - elevation[lowest];
+            if drop <= 0.0 {
+                //stuck in a pit, fill it in
+                elevation[index] += sediment;
+                break;
+            }
+            let capacity = drop * water * CAPACITY;
+            if sediment > capacity {
+                let deposit = (sediment - capacity) * DEPOSITION;
+                elevation[index] += deposit;
+                sediment -= deposit;
+            } else {
+                //never dig below the next cell, or the droplet would make its own pit, nor below sea level
+                let taken = ((capacity - sediment) * EROSION).min(drop).min(elevation[index] - ocean_cutoff);
+                elevation[index] -= taken;
+                sediment += taken;
+            }
+            index = lowest;
+            water *= 1.0 - EVAPORATION;
+        }
+    }
+    //loose soil slumps into its neighbors, which softens the grooves droplets leave along the hex directions.
+    //Like the droplets it never moves the coast.
+    let slumped: Vec<f32> = (0..elevation.len())
+        .map(|index| {
+            let around: Vec<f32> = neighbors.0[index].iter().filter(|&&neighbor| neighbor != NO_CELL).map(|&neighbor| elevation[neighbor as usize]).collect();
+            let mean = around.iter().sum::<f32>() / around.len() as f32;
+            let slumped = elevation[index] * (1.0 - SLUMP) + mean * SLUMP;
+            match is_land(elevation[index], config) {
+                true => slumped.max(ocean_cutoff),
+                false => slumped.min(ocean_cutoff - f32::EPSILON),
+            }
+        })
+        .collect();
+    elevation.copy_from_slice(&slumped);
+}
+
+//detail is noise between 0 and 1 for every cell, laid over the plates
+pub fn generate(config: &SimConfig, seed: WorldSeed, detail: &[f32]) -> Vec<f32> {
+    let mut rng = rng_for(seed, RngStream::Tectonics, 0, 0);
+    let neighbors = Neighbors::new(&config.hex_grid());
+    let plates: Vec<Plate> = (0..config.plates)
+        .map(|_| {
+            let continental = rng.gen_bool(CONTINENTAL_CHANCE);
+            let base = if continental { rng.gen_range(0.55..0.65) } else { rng.gen_range(0.3..0.4) };
+            let (angle, speed) = (rng.gen_range(0.0..std::f32::consts::TAU), rng.gen_range(0.2..1.0));
+            Plate { continental, base, velocity: (angle.cos() * speed, angle.sin() * speed) }
+        })
+        .collect();
+    let plate = assign_plates(&neighbors, plates.len(), detail, &mut rng);
+
+    //every cell takes the effect of the nearest boundary, fading out over BOUNDARY_REACH cells
+    let mut effect = vec![0.0; plate.len()];
+    let mut distance = vec![u32::MAX; plate.len()];
+    let mut queue = VecDeque::new();
+    for index in 0..plate.len() {
+        if let Some(boundary) = boundary_effect(index, &plate, &plates, &neighbors) {
+            effect[index] = boundary;
+            distance[index] = 0;
+            queue.push_back(index);
+        }
+    }
+    while let Some(index) = queue.pop_front() {
+        if distance[index] == BOUNDARY_REACH {
+            continue;
+        }
+        for &neighbor in &neighbors.0[index] {
+            //boundary effects stay on their own plate, the other side has its own
+            if neighbor != NO_CELL && distance[neighbor as usize] == u32::MAX && plate[neighbor as usize] == plate[index] {
+                distance[neighbor as usize] = distance[index] + 1;
+                effect[neighbor as usize] = effect[index];
+                queue.push_back(neighbor as usize);
+            }
+        }
+    }
+
+    let mut elevation: Vec<f32> = (0..plate.len())
+        .map(|index| {
+            let falloff = match distance[index] {
+                u32::MAX => 0.0,
+                distance => (1.0 - distance as f32 / (BOUNDARY_REACH + 1) as f32).powi(2),
+            };
+            plates[plate[index]].base + effect[index] * falloff + (detail[index] - 0.5) * DETAIL
+        })
+        .collect();
+    let droplets = config.erosion_passes as usize * plate.len() / 4;
+    erode(&mut elevation, &neighbors, droplets, config, &mut rng);
+    elevation.iter().map(|elevation| elevation.clamp(0.0, 1.0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_config() -> SimConfig {
+        let mut config = SimConfig::default();
+        (config.width, config.height) = (48, 32);
+        config
+    }
+
+    fn noise(config: &SimConfig, seed: u64) -> Vec<f32> {
+        let mut rng = rng_for(WorldSeed(seed), RngStream::Terrain, 0, 0);
+        (0..config.width * config.height).map(|_| rng.gen_range(0.0..1.0)).collect()
+    }
+
+    #[test]
+    fn every_cell_gets_a_plate() {
+        let config = small_config();
+        let mut rng = rng_for(WorldSeed(1), RngStream::Tectonics, 0, 0);
+        let plate = assign_plates(&Neighbors::new(&config.hex_grid()), 7, &noise(&config, 1), &mut rng);
+        assert_eq!(plate.len(), config.width * config.height);
+        assert!(plate.iter().all(|&plate| plate < 7));
+    }
+
+    #[test]
+    fn generate_is_the_same_for_a_seed_and_stays_in_range() {
+        let config = small_config();
+        let detail = noise(&config, 2);
+        let elevation = generate(&config, WorldSeed(3), &detail);
+        assert!(elevation.iter().all(|elevation| (0.0..=1.0).contains(elevation)));
+        assert!(elevation == generate(&config, WorldSeed(3), &detail));
+        assert!(elevation != generate(&config, WorldSeed(4), &detail));
+    }
+
+    #[test]
+    fn erosion_never_moves_the_coast() {
+        let config = small_config();
+        let neighbors = Neighbors::new(&config.hex_grid());
+        let before = noise(&config, 5);
+        let mut after = before.clone();
+        let mut rng = rng_for(WorldSeed(5), RngStream::Tectonics, 0, 0);
+        erode(&mut after, &neighbors, 4 * before.len(), &config, &mut rng);
+        assert!(before != after);
+        for (index, (before, after)) in before.iter().zip(&after).enumerate() {
+            assert_eq!(is_land(*before, &config), is_land(*after, &config), "cell {} went from {} to {}", index, before, after);
+        }
+    }
+}
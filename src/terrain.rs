@@ -0,0 +1,195 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::config::SimConfig;
+use crate::rng::WorldSeed;
+use crate::worldgen::{ELEVATION, LAYER_NAMES, MOISTURE, TEMPERATURE};
+use crate::{Grid, VARIABLES};
+
+//a generated world written out for other tools: heightmap.png (16 bit grayscale, one pixel per cell, top row first),
+//<layer>.f32 for every grid layer (little endian floats indexed by y * width + x) and terrain.toml describing them.
+//Only elevation, moisture and temperature are read back, the rest is derived again under the current config,
+//so reading the files with the config they were written with gives exactly the same Grid.
+const SIDECAR: &str = "terrain.toml";
+const HEIGHTMAP: &str = "heightmap.png";
+//bumped whenever the files change meaning
+pub const TERRAIN_VERSION: u32 = 1;
+const IMPORTED: [usize; 3] = [ELEVATION, MOISTURE, TEMPERATURE];
+
+//terrain.toml. The config is the one the terrain was generated with, kept so it can be made again from the seed.
+#[derive(Serialize, Deserialize)]
+struct TerrainInfo {
+    version: u32,
+    seed: u64,
+    width: usize,
+    height: usize,
+    layers: Vec<String>,
+    config: SimConfig,
+}
+
+#[derive(Debug)]
+pub enum TerrainError {
+    Io(String, std::io::Error),
+    Parse(String),
+    Version(u32),
+    Layer(String),
+    Write(String, String),
+}
+
+impl fmt::Display for TerrainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TerrainError::Io(path, e) => write!(f, "{}: {}", path, e),
+            TerrainError::Parse(e) => write!(f, "could not parse {}: {}", SIDECAR, e),
+            TerrainError::Version(v) => write!(f, "terrain version {} is not supported (expected {})", v, TERRAIN_VERSION),
+            TerrainError::Layer(e) => write!(f, "{}", e),
+            TerrainError::Write(path, e) => write!(f, "could not write {}: {}", path, e),
+        }
+    }
+}
+
+impl std::error::Error for TerrainError {}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> TerrainError + '_ {
+    move |e| TerrainError::Io(path.display().to_string(), e)
+}
+
+pub fn write_terrain(dir: &Path, grid: &Grid, config: &SimConfig, seed: WorldSeed) -> Result<(), TerrainError> {
+    let (width, height) = (config.width, config.height);
+    fs::create_dir_all(dir).map_err(io_error(dir))?;
+
+    for (layer, name) in LAYER_NAMES.iter().enumerate() {
+        let path = dir.join(format!("{}.f32", name));
+        let bytes: Vec<u8> = (0..width * height).flat_map(|index| grid.data[index % width][index / width][layer].to_le_bytes()).collect();
+        fs::write(&path, bytes).map_err(io_error(&path))?;
+    }
+
+    let path = dir.join(HEIGHTMAP);
+    let pixels: Vec<u8> = (0..height)
+        .rev()
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| ((grid.data[x][y][ELEVATION].clamp(0.0, 1.0) * 65535.0).round() as u16).to_be_bytes())
+        .collect();
+    let file = File::create(&path).map_err(io_error(&path))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    let result = encoder.write_header().and_then(|mut writer| {
+        writer.write_image_data(&pixels)?;
+        writer.finish()
+    });
+    result.map_err(|e| TerrainError::Write(path.display().to_string(), e.to_string()))?;
+
+    let info = TerrainInfo { version: TERRAIN_VERSION, seed: seed.0, width, height, layers: LAYER_NAMES.iter().map(|name| name.to_string()).collect(), config: config.clone() };
+    let path = dir.join(SIDECAR);
+    let text = toml::to_string(&info).map_err(|e| TerrainError::Write(path.display().to_string(), e.to_string()))?;
+    fs::write(&path, text).map_err(io_error(&path))
+}
+
+//elevation, moisture and temperature written by write_terrain, and the map size. The other layers are left at 0
+//for worldgen::derive once the config is settled.
+pub fn read_terrain(dir: &Path) -> Result<(Grid, usize, usize), TerrainError> {
+    let path = dir.join(SIDECAR);
+    let text = fs::read_to_string(&path).map_err(io_error(&path))?;
+    let info: TerrainInfo = toml::from_str(&text).map_err(|e| TerrainError::Parse(e.to_string()))?;
+    if info.version != TERRAIN_VERSION {
+        return Err(TerrainError::Version(info.version));
+    }
+    let (width, height) = (info.width, info.height);
+
+    let mut data = vec![vec![vec![0.0; VARIABLES]; height]; width];
+    for layer in IMPORTED {
+        let name = LAYER_NAMES[layer];
+        if !info.layers.iter().any(|listed| listed == name) {
+            return Err(TerrainError::Layer(format!("{} has no {} layer", path.display(), name)));
+        }
+        let path = dir.join(format!("{}.f32", name));
+        let bytes = fs::read(&path).map_err(io_error(&path))?;
+        if bytes.len() != width * height * 4 {
+            return Err(TerrainError::Layer(format!("{} should hold {}x{} floats", path.display(), width, height)));
+        }
+        for (index, value) in bytes.chunks(4).enumerate() {
+            let value = f32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+            //every layer is between 0 and 1, and rivers::drainage sorts elevations by their bits, which needs them non-negative
+            if !(0.0..=1.0).contains(&value) {
+                return Err(TerrainError::Layer(format!("{} has {} at ({}, {}), outside [0, 1]", path.display(), value, index % width, index / width)));
+            }
+            data[index % width][index / width][layer] = value;
+        }
+    }
+    Ok((Grid { data }, width, height))
+}
+
+//--export-terrain, once the world exists
+#[derive(Resource)]
+pub struct TerrainExport(pub std::path::PathBuf);
+
+pub fn export_terrain_system(export: Res<TerrainExport>, grid: Res<Grid>, config: Res<SimConfig>, seed: Res<WorldSeed>) {
+    match write_terrain(&export.0, &grid, &config, *seed) {
+        Ok(()) => println!("Exported the terrain to {}", export.0.display()),
+        Err(e) => eprintln!("Could not export the terrain: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worldgen;
+
+    fn export(name: &str) -> (std::path::PathBuf, SimConfig, Grid) {
+        let mut config = SimConfig::default();
+        (config.width, config.height) = (24, 16);
+        let grid = Grid { data: worldgen::generate(&config, VARIABLES, WorldSeed(9), None) };
+        let dir = std::env::temp_dir().join(format!("empires-terrain-{}-{}", name, std::process::id()));
+        write_terrain(&dir, &grid, &config, WorldSeed(9)).unwrap();
+        (dir, config, grid)
+    }
+
+    #[test]
+    fn read_terrain_derives_the_rest_again() {
+        let (dir, mut config, grid) = export("read");
+        let (mut read, width, height) = read_terrain(&dir).unwrap();
+        assert_eq!((width, height), (config.width, config.height));
+        worldgen::derive(&mut read.data, &config);
+        assert_eq!(read.data, grid.data);
+
+        //a higher sea level floods the exported terrain instead of keeping its old coast
+        config.ocean_cutoff = 0.9;
+        worldgen::derive(&mut read.data, &config);
+        assert!(read.data.iter().flatten().all(|cell| worldgen::Biome::from_layer(cell[worldgen::BIOME]) == worldgen::Biome::Ocean));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_terrain_checks_the_version() {
+        let (dir, _, _) = export("version");
+        let path = dir.join(SIDECAR);
+        let text = fs::read_to_string(&path).unwrap().replace(&format!("version = {}", TERRAIN_VERSION), "version = 0");
+        fs::write(&path, text).unwrap();
+        assert!(matches!(read_terrain(&dir), Err(TerrainError::Version(0))));
+        let text = fs::read_to_string(&path).unwrap().replace("version = 0\n", "");
+        fs::write(&path, text).unwrap();
+        assert!(matches!(read_terrain(&dir), Err(TerrainError::Parse(e)) if e.contains("version")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_terrain_rejects_values_outside_the_unit_range() {
+        for bad in [f32::NAN, f32::INFINITY, -0.25, 1.5] {
+            let (dir, _, _) = export("range");
+            let path = dir.join("elevation.f32");
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[8..12].copy_from_slice(&bad.to_le_bytes());
+            fs::write(&path, bytes).unwrap();
+            match read_terrain(&dir) {
+                Err(TerrainError::Layer(e)) => assert!(e.contains("at (2, 0), outside [0, 1]"), "{}", e),
+                _ => panic!("{} was read", bad),
+            }
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+use flate2::Crc;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::SimConfig;
+use crate::export::{map_pixels, scale_pixels, write_png};
+use crate::map::MapData;
+use crate::render::map_colors;
+use crate::{GameData, Grid, RenderMode};
+
+enum Output {
+    //one numbered PNG per frame, written as soon as it's drawn
+    Sequence(PathBuf),
+    //an APNG, also written a frame at a time. Its header needs the frame count, which is only known once the run ends,
+    //so it starts out as a placeholder and is patched by finish. A run that dies early still leaves its frames on disk.
+    Animation { path: PathBuf, writer: png::Writer<File> },
+}
+
+//a frame every `every` ticks in one render mode, from --timelapse
+#[derive(Resource)]
+pub struct Timelapse {
+    output: Option<Output>,
+    mode: RenderMode,
+    every: u64,
+    scale: usize,
+    frames: usize,
+    //frame size in pixels, after scaling
+    size: (usize, usize),
+}
+
+impl Timelapse {
+    //a path ending in .png or .apng is written as one animation, anything else is a directory for the sequence
+    pub fn create(path: &Path, mode: RenderMode, every: u64, scale: usize, fps: u16, config: &SimConfig) -> Result<Self, png::EncodingError> {
+        let animated = path.extension().is_some_and(|ext| ext == "png" || ext == "apng");
+        let size = (2 * config.width * scale, config.height * scale);
+        let output = match animated {
+            true => {
+                //fail now rather than after a whole run. The file is removed again if no frames make it into it.
+                //Frames go straight to the file, unbuffered, so each one is on disk once written.
+                let mut encoder = png::Encoder::new(File::create(path)?, size.0 as u32, size.1 as u32);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                //0 plays loops forever
+                encoder.set_animated(u32::MAX, 0)?;
+                encoder.set_frame_delay(1, fps)?;
+                Output::Animation { path: path.to_path_buf(), writer: encoder.write_header()? }
+            }
+            false => {
+                std::fs::create_dir_all(path)?;
+                Output::Sequence(path.to_path_buf())
+            }
+        };
+        Ok(Timelapse { output: Some(output), mode, every, scale, frames: 0, size })
+    }
+
+    fn capture(&mut self, pixels: Vec<u8>) {
+        let (width, height) = self.size;
+        let result = match &mut self.output {
+            Some(Output::Sequence(dir)) => {
+                let path = dir.join(format!("frame{:06}.png", self.frames));
+                write_png(&path, &pixels, width, height)
+            }
+            Some(Output::Animation { writer, .. }) => writer.write_image_data(&pixels),
+            None => return,
+        };
+        match result {
+            Ok(()) => self.frames += 1,
+            Err(e) => {
+                eprintln!("Could not write a time-lapse frame, recording stopped: {}", e);
+                if let Some(Output::Animation { path, writer }) = self.output.take() {
+                    drop(writer);
+                    discard(&path);
+                }
+            }
+        }
+    }
+
+    //a sequence is already on disk, an animation only needs its ending and the real frame count
+    fn finish(&mut self) {
+        match self.output.take() {
+            Some(Output::Sequence(dir)) => println!("Wrote {} time-lapse frames to {}", self.frames, dir.display()),
+            Some(Output::Animation { path, writer }) if self.frames > 0 => match writer.finish().and_then(|()| set_frame_count(&path, self.frames as u32)) {
+                Ok(()) => println!("Wrote a {} frame time-lapse to {}", self.frames, path.display()),
+                Err(e) => eprintln!("Could not write {}: {}", path.display(), e),
+            },
+            Some(Output::Animation { path, writer }) => {
+                drop(writer);
+                println!("No time-lapse frames were recorded");
+                discard(&path);
+            }
+            None => {}
+        }
+    }
+}
+
+//the acTL chunk comes right after the header. Its data starts with the frame count, and like every chunk it ends in
+//a CRC of its type and data.
+fn set_frame_count(path: &Path, frames: u32) -> Result<(), png::EncodingError> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let mut head = [0; 64];
+    let read = file.read(&mut head)?;
+    let at = head[..read].windows(4).position(|window| window == b"acTL").filter(|at| at + 16 <= read);
+    let Some(at) = at else {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "no animation control chunk").into());
+    };
+    head[at + 4..at + 8].copy_from_slice(&frames.to_be_bytes());
+    let mut crc = Crc::new();
+    crc.update(&head[at..at + 12]);
+    head[at + 12..at + 16].copy_from_slice(&crc.sum().to_be_bytes());
+    file.seek(SeekFrom::Start(at as u64 + 4))?;
+    file.write_all(&head[at + 4..at + 16])?;
+    Ok(())
+}
+
+//a file with no frames is only the header made by create
+fn discard(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path) {
+        eprintln!("Could not remove {}: {}", path.display(), e);
+    }
+}
+
+//runs at the end of every tick when --timelapse is given, and once at startup for the first frame
+pub fn timelapse_frame_system(mut timelapse: ResMut<Timelapse>, grid: Res<Grid>, map: Res<MapData>, game_data: Res<GameData>, config: Res<SimConfig>) {
+    if !game_data.tick.is_multiple_of(timelapse.every) {
+        return;
+    }
+    let colors = map_colors(timelapse.mode, &grid, &map, &game_data, &config, None);
+    let pixels = scale_pixels(&map_pixels(&colors, config.width, config.height), 2 * config.width, timelapse.scale);
+    timelapse.capture(pixels);
+}
+
+//the app is closing, either the window was closed or a headless run is done
+pub fn finish_timelapse_system(mut timelapse: ResMut<Timelapse>, mut exit: EventReader<AppExit>) {
+    if exit.read().next().is_some() {
+        timelapse.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{run_ticks, small_world};
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("empires-{}-{}.png", name, std::process::id()))
+    }
+
+    #[test]
+    fn an_animation_without_frames_leaves_no_file() {
+        let mut config = SimConfig::default();
+        (config.width, config.height) = (4, 3);
+        let path = path("empty");
+        let mut timelapse = Timelapse::create(&path, RenderMode::EmpireView, 10, 1, 15, &config).unwrap();
+        assert!(path.exists());
+        timelapse.finish();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn an_animation_is_written_as_the_run_goes() {
+        let path = path("frames");
+        let mut app = small_world(3);
+        let config = app.world().resource::<SimConfig>().clone();
+        app.insert_resource(Timelapse::create(&path, RenderMode::StrengthView, 5, 2, 15, &config).unwrap());
+        //the first frame at tick 0, then ticks 5 and 10
+        let mut sizes = Vec::new();
+        for ticks in [0, 5, 5] {
+            run_ticks(&mut app, ticks);
+            app.world_mut().run_system_once(timelapse_frame_system);
+            sizes.push(std::fs::metadata(&path).unwrap().len());
+        }
+        assert!(sizes.windows(2).all(|pair| pair[0] < pair[1]), "frames weren't written as they came: {:?}", sizes);
+        app.world_mut().resource_mut::<Timelapse>().finish();
+
+        let mut reader = png::Decoder::new(File::open(&path).unwrap()).read_info().unwrap();
+        assert_eq!(reader.info().animation_control().map(|control| control.num_frames), Some(3));
+        assert_eq!(reader.info().size(), (2 * 96 * 2, 64 * 2));
+        let mut frame = vec![0; reader.output_buffer_size()];
+        for _ in 0..3 {
+            reader.next_frame(&mut frame).unwrap();
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+use crate::config::SimConfig;
+use crate::map::{Neighbors, NO_CELL};
+
+//every connected stretch of water is one body, sized by how many cells it covers. Lakes are too small to sail:
+//they don't count as coast, and boats are never launched onto them. Stored in the grid as its index.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaterBody {
+    Land,
+    Lake,
+    Sea,
+    Ocean,
+}
+
+impl WaterBody {
+    const ALL: [WaterBody; 4] = [WaterBody::Land, WaterBody::Lake, WaterBody::Sea, WaterBody::Ocean];
+
+    pub fn from_layer(value: f32) -> WaterBody {
+        WaterBody::ALL[(value.round() as usize).min(WaterBody::ALL.len() - 1)]
+    }
+
+    pub fn layer(self) -> f32 {
+        self as usize as f32
+    }
+
+    pub fn navigable(self) -> bool {
+        matches!(self, WaterBody::Sea | WaterBody::Ocean)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WaterBody::Land => "land",
+            WaterBody::Lake => "lake",
+            WaterBody::Sea => "sea",
+            WaterBody::Ocean => "ocean",
+        }
+    }
+}
+
+//a cell right at the ocean cutoff is land. Everything that splits land from water goes through here.
+pub fn is_land(elevation: f32, config: &SimConfig) -> bool {
+    elevation >= config.ocean_cutoff
+}
+
+//flood fill every body of water below the ocean cutoff, indexed y * width + x
+pub fn classify(elevation: &[f32], config: &SimConfig) -> Vec<WaterBody> {
+    let neighbors = Neighbors::new(&config.hex_grid());
+    let mut bodies = vec![WaterBody::Land; elevation.len()];
+    let mut seen: Vec<bool> = elevation.iter().map(|&elevation| is_land(elevation, config)).collect();
+    let mut body = Vec::new();
+    for start in 0..elevation.len() {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        body.clear();
+        body.push(start);
+        let mut next = 0;
+        while let Some(&index) = body.get(next) {
+            next += 1;
+            for &neighbor in &neighbors.0[index] {
+                if neighbor != NO_CELL && !seen[neighbor as usize] {
+                    seen[neighbor as usize] = true;
+                    body.push(neighbor as usize);
+                }
+            }
+        }
+        let kind = match body.len() {
+            size if size <= config.lake_size => WaterBody::Lake,
+            size if size <= config.sea_size => WaterBody::Sea,
+            _ => WaterBody::Ocean,
+        };
+        for &index in &body {
+            bodies[index] = kind;
+        }
+    }
+    bodies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_sizes_up_each_body() {
+        let mut config = SimConfig::default();
+        (config.width, config.height, config.lake_size, config.sea_size) = (12, 6, 1, 4);
+        let width = config.width;
+        let at = |x: usize, y: usize| y * width + x;
+        //an ocean two columns wide (12 cells), an inland sea of 4, a one cell lake and a cell right at the cutoff
+        let mut elevation = vec![0.8; width * config.height];
+        for y in 0..config.height {
+            elevation[at(0, y)] = 0.1;
+            elevation[at(1, y)] = 0.1;
+        }
+        for (x, y) in [(4, 2), (5, 2), (4, 3), (5, 3)] {
+            elevation[at(x, y)] = 0.3;
+        }
+        elevation[at(9, 2)] = 0.2;
+        elevation[at(9, 4)] = config.ocean_cutoff;
+
+        let bodies = classify(&elevation, &config);
+        assert_eq!(bodies[at(0, 3)], WaterBody::Ocean);
+        assert_eq!(bodies[at(4, 2)], WaterBody::Sea);
+        assert_eq!(bodies[at(9, 2)], WaterBody::Lake);
+        assert_eq!(bodies[at(9, 4)], WaterBody::Land);
+        for (body, size) in [(WaterBody::Ocean, 12), (WaterBody::Sea, 4), (WaterBody::Lake, 1)] {
+            assert_eq!(bodies.iter().filter(|&&found| found == body).count(), size, "{}", body.name());
+        }
+
+        //land is coast when a neighbor can be sailed, so the shores of the ocean and the sea count but the lake's don't
+        let neighbors = Neighbors::new(&config.hex_grid());
+        let coast = |index: usize| neighbors.0[index].iter().any(|&next| next != NO_CELL && bodies[next as usize].navigable());
+        let shore = |water: usize| neighbors.0[water].iter().map(|&next| next as usize).filter(|&next| bodies[next] == WaterBody::Land).collect::<Vec<_>>();
+        assert!(shore(at(1, 3)).into_iter().all(coast));
+        assert!(shore(at(4, 2)).into_iter().all(coast));
+        let lake_shore = shore(at(9, 2));
+        assert_eq!(lake_shore.len(), 6);
+        assert!(!lake_shore.into_iter().any(coast));
+    }
+}
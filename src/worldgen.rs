@@ -0,0 +1,286 @@
+use noise::{NoiseFn, Simplex};
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Generator, SimConfig};
+use crate::import::Picture;
+use crate::rivers;
+use crate::rng::{rng_for, RngStream, WorldSeed};
+use crate::tectonics;
+use crate::water;
+
+//what each slot of a Grid cell holds
+pub const ELEVATION: usize = 0;
+pub const MOISTURE: usize = 1;
+pub const TEMPERATURE: usize = 2;
+pub const BIOME: usize = 3;
+//see rivers.rs
+pub const RIVER: usize = 4;
+//a water::WaterBody, stored as its index
+pub const WATER: usize = 5;
+pub const LAYERS: usize = 6;
+pub const LAYER_NAMES: [&str; LAYERS] = ["elevation", "moisture", "temperature", "biome", "river", "water"];
+
+//moisture only needs broad wet and dry regions, two octaves are plenty
+const MOISTURE_OCTAVES: u32 = 2;
+
+//picked from temperature and moisture once the other layers exist. Stored in the grid as its index.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Biome {
+    Ocean,
+    Ice,
+    Tundra,
+    Desert,
+    Grassland,
+    Forest,
+    Jungle,
+}
+
+impl Biome {
+    const ALL: [Biome; 7] = [Biome::Ocean, Biome::Ice, Biome::Tundra, Biome::Desert, Biome::Grassland, Biome::Forest, Biome::Jungle];
+
+    pub fn from_layer(value: f32) -> Biome {
+        Biome::ALL[(value.round() as usize).min(Biome::ALL.len() - 1)]
+    }
+
+    pub fn layer(self) -> f32 {
+        self as usize as f32
+    }
+
+    pub fn classify(elevation: f32, moisture: f32, temperature: f32, config: &SimConfig) -> Biome {
+        if !water::is_land(elevation, config) {
+            Biome::Ocean
+        } else if temperature < 0.15 {
+            Biome::Ice
+        } else if temperature < 0.3 {
+            Biome::Tundra
+        } else if moisture < 0.4 {
+            Biome::Desert
+        } else if moisture > 0.6 && temperature > 0.7 {
+            Biome::Jungle
+        } else if moisture > 0.5 {
+            Biome::Forest
+        } else {
+            Biome::Grassland
+        }
+    }
+
+    //how well land feeds its people, scales terrain_factor. Grassland is the best there is.
+    pub fn growth(self) -> f32 {
+        match self {
+            Biome::Ocean | Biome::Grassland => 1.0,
+            Biome::Ice => 0.7,
+            Biome::Tundra => 0.85,
+            Biome::Desert => 0.8,
+            Biome::Forest => 0.95,
+            Biome::Jungle => 0.9,
+        }
+    }
+
+    //how much a cell is worth reinforcing, scales need_factor
+    pub fn need(self) -> f32 {
+        match self {
+            Biome::Ocean | Biome::Grassland | Biome::Jungle => 1.0,
+            Biome::Ice => 0.5,
+            Biome::Tundra | Biome::Desert => 0.8,
+            Biome::Forest => 1.1,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Biome::Ocean => "ocean",
+            Biome::Ice => "ice",
+            Biome::Tundra => "tundra",
+            Biome::Desert => "desert",
+            Biome::Grassland => "grassland",
+            Biome::Forest => "forest",
+            Biome::Jungle => "jungle",
+        }
+    }
+}
+
+//fractal noise: each octave is `lacunarity` times the frequency of the last and `persistence` times its weight.
+//The largest octave has features about `scale` cells across.
+struct Octaves {
+    noise: Vec<Simplex>,
+    scale: f64,
+    lacunarity: f64,
+    persistence: f32,
+}
+
+impl Octaves {
+    fn new(rng: &mut impl Rng, octaves: u32, scale: f32, lacunarity: f32, persistence: f32) -> Self {
+        Octaves {
+            noise: (0..octaves).map(|_| Simplex::new(rng.gen::<u32>())).collect(),
+            scale: scale as f64,
+            lacunarity: lacunarity as f64,
+            persistence,
+        }
+    }
+
+    //between 0 and 1, 0.5 on average
+    fn get(&self, x: usize, y: usize) -> f32 {
+        let mut x = x as f64;
+        if y % 2 == 1 {
+            //offset x by .5;
+            x += 0.5;
+        }
+        let (mut sum, mut total, mut weight, mut scale) = (0.0, 0.0, 1.0, self.scale);
+        for noise in &self.noise {
+            sum += noise.get([x / scale, y as f64 / scale]) as f32 * weight;
+            total += weight;
+            weight *= self.persistence;
+            scale /= self.lacunarity;
+        }
+        sum / total * 0.5 + 0.5
+    }
+
+    //the map wraps horizontally, so near the edge the value is blended with the opposite side
+    fn get_seamless(&self, x: usize, y: usize, width: usize, loop_dist: usize) -> f32 {
+        let value = self.get(x, y);
+        if x >= loop_dist && x <= width - loop_dist {
+            return value;
+        }
+        let opp_x = width - x - 1;
+        let opp_prop = x.min(opp_x) as f32 / loop_dist as f32 * -0.5 + 0.5;
+        value * (1.0 - opp_prop) + self.get(opp_x, y) * opp_prop
+    }
+}
+
+//1 at the equator (the middle row), 0 at the poles, and colder the higher the land
+pub fn temperature(y: usize, elevation: f32, config: &SimConfig) -> f32 {
+    let latitude = (y as f32 / (config.height - 1) as f32 * 2.0 - 1.0).abs();
+    let altitude = ((elevation - config.ocean_cutoff) / (1.0 - config.ocean_cutoff)).max(0.0);
+    (1.0 - latitude - altitude * config.lapse_rate).clamp(0.0, 1.0)
+}
+
+//every layer of every cell, indexed [x][y][layer]. variables has to be at least LAYERS.
+//With a heightmap the elevation comes from it, the climate is still generated.
+pub fn generate(config: &SimConfig, variables: usize, seed: WorldSeed, heightmap: Option<&Picture>) -> Vec<Vec<Vec<f32>>> {
+    let (width, height, loop_dist) = (config.width, config.height, config.loop_dist);
+    let mut rng = rng_for(seed, RngStream::Terrain, 0, 0);
+    let elevation = Octaves::new(&mut rng, config.octaves, config.continent_scale, config.lacunarity, config.persistence);
+    let moisture = Octaves::new(&mut rng, MOISTURE_OCTAVES, config.moisture_scale, 2.0, 0.5);
+    //plates wrap around the map on their own, the same noise only roughens them up
+    let plates = (heightmap.is_none() && config.generator == Generator::Tectonic).then(|| {
+        let detail: Vec<f32> = (0..width * height).into_par_iter().map(|index| elevation.get_seamless(index % width, index / width, width, loop_dist)).collect();
+        tectonics::generate(config, seed, &detail)
+    });
+
+    let mut data = vec![vec![vec![0.0; variables]; height]; width];
+    data.par_iter_mut().enumerate().for_each(|(x, row)| {
+        row.iter_mut().enumerate().for_each(|(y, cell)| {
+            cell[ELEVATION] = match (heightmap, &plates) {
+                (Some(heightmap), _) => heightmap.elevation((x, y), config),
+                (None, Some(plates)) => plates[y * width + x],
+                (None, None) => elevation.get_seamless(x, y, width, loop_dist),
+            };
+            cell[MOISTURE] = moisture.get_seamless(x, y, width, loop_dist);
+            cell[TEMPERATURE] = temperature(y, cell[ELEVATION], config);
+        });
+    });
+    derive(&mut data, config);
+    data
+}
+
+//biome, rivers and bodies of water follow from elevation, moisture and temperature under the config's cutoffs,
+//so terrain made elsewhere (see terrain.rs) only needs those three
+pub fn derive(data: &mut [Vec<Vec<f32>>], config: &SimConfig) {
+    let (width, height) = (config.width, config.height);
+    data.par_iter_mut().for_each(|row| {
+        row.iter_mut().for_each(|cell| {
+            cell[BIOME] = Biome::classify(cell[ELEVATION], cell[MOISTURE], cell[TEMPERATURE], config).layer();
+        });
+    });
+
+    //rivers and bodies of water need the whole map at once
+    let layer = |layer: usize| -> Vec<f32> { (0..width * height).map(|index| data[index % width][index / width][layer]).collect() };
+    let elevation = layer(ELEVATION);
+    let flow = rivers::flow(&elevation, &layer(MOISTURE), config);
+    let water = water::classify(&elevation, config);
+    for (index, (flow, water)) in flow.into_iter().zip(water).enumerate() {
+        data[index % width][index / width][RIVER] = flow;
+        data[index % width][index / width][WATER] = water.layer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_config() -> SimConfig {
+        let mut config = SimConfig::default();
+        (config.width, config.height, config.loop_dist) = (48, 32, 8);
+        config
+    }
+
+    #[test]
+    fn classify_follows_the_thresholds() {
+        let config = SimConfig::default();
+        let cutoff = config.ocean_cutoff;
+        //(elevation, moisture, temperature)
+        let cases = [
+            ((cutoff - 0.01, 0.5, 0.5), Biome::Ocean),
+            ((cutoff, 0.55, 0.5), Biome::Forest),
+            ((0.7, 0.9, 0.1), Biome::Ice),
+            ((0.7, 0.9, 0.15), Biome::Tundra),
+            ((0.7, 0.2, 0.29), Biome::Tundra),
+            ((0.7, 0.39, 0.3), Biome::Desert),
+            ((0.7, 0.4, 0.9), Biome::Grassland),
+            ((0.7, 0.5, 0.9), Biome::Grassland),
+            ((0.7, 0.6, 0.9), Biome::Forest),
+            ((0.7, 0.61, 0.7), Biome::Forest),
+            ((0.7, 0.61, 0.71), Biome::Jungle),
+        ];
+        for ((elevation, moisture, temperature), biome) in cases {
+            assert_eq!(Biome::classify(elevation, moisture, temperature, &config), biome, "{} {} {}", elevation, moisture, temperature);
+            assert_eq!(Biome::from_layer(biome.layer()), biome);
+        }
+    }
+
+    #[test]
+    fn temperature_falls_toward_the_poles_and_with_altitude() {
+        let mut config = small_config();
+        config.height = 33;
+        let coast = config.ocean_cutoff;
+        assert_eq!(temperature(16, coast, &config), 1.0);
+        assert_eq!(temperature(0, coast, &config), 0.0);
+        assert_eq!(temperature(32, coast, &config), 0.0);
+        assert!(temperature(8, coast, &config) < 1.0 && temperature(8, coast, &config) > 0.0);
+        //the sea floor isn't colder than the sea
+        assert_eq!(temperature(16, 0.0, &config), 1.0);
+        assert!(temperature(16, 0.9, &config) < temperature(16, 0.7, &config));
+        assert!(temperature(16, 0.7, &config) < 1.0);
+    }
+
+    #[test]
+    fn octaves_stay_in_range_and_meet_across_the_seam() {
+        let config = small_config();
+        let mut rng = rng_for(WorldSeed(2), RngStream::Terrain, 0, 0);
+        let octaves = Octaves::new(&mut rng, 4, 20.0, 2.0, 0.5);
+        for x in 0..config.width {
+            for y in 0..config.height {
+                let value = octaves.get(x, y);
+                assert!((0.0..=1.0).contains(&value), "{} at ({}, {})", value, x, y);
+            }
+        }
+        for y in 0..config.height {
+            //the two edge columns are both half their own value and half the other's, so they match
+            let (left, right) = (octaves.get_seamless(0, y, config.width, config.loop_dist), octaves.get_seamless(config.width - 1, y, config.width, config.loop_dist));
+            assert!((left - right).abs() < 1e-6, "{} and {} at row {}", left, right, y);
+            //away from the edges nothing is blended
+            assert_eq!(octaves.get_seamless(config.width / 2, y, config.width, config.loop_dist), octaves.get(config.width / 2, y));
+        }
+    }
+
+    #[test]
+    fn generate_is_the_same_for_a_seed() {
+        let config = small_config();
+        let world = generate(&config, LAYERS, WorldSeed(6), None);
+        assert!(world == generate(&config, LAYERS, WorldSeed(6), None));
+        assert!(world != generate(&config, LAYERS, WorldSeed(7), None));
+        assert!(world.iter().flatten().all(|cell| (0.0..=1.0).contains(&cell[ELEVATION]) && (0.0..=1.0).contains(&cell[TEMPERATURE])));
+    }
+}